@@ -20,15 +20,203 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 
 */
 
-use rand::{
+use super::rng::{
+    distributions::{Distribution, Standard},
     Rng,
     SeedableRng,
 };
+use std::cell::RefCell;
 
-pub fn get_rng() -> rand::rngs::SmallRng {
-    rand::rngs::SmallRng::from_rng(&mut rand::thread_rng()).unwrap()
+/*
+The generator behind every rng we hand out. It is pinned to PCG64 rather
+than left to SmallRng, whose algorithm rand may swap out from one release
+to the next or pick differently per platform, so the same seed draws the
+same numbers on every platform and every build.
+Integer ranges are always drawn as fixed-width integers (never usize),
+so 32-bit targets such as wasm make the same choices as 64-bit ones.
+The rng module draws exactly what rand 0.8 and rand_pcg did, so seeds
+from before it still grow the same fish.
+*/
+pub type GameRng = super::rng::Pcg64;
+
+struct SeededRngs {
+    //Where the big decisions come from: how many layers, which generators, where.
+    structure: Option<GameRng>,
+    //Where the fine details of each generator come from, if they have a seed of their own.
+    detail: Option<GameRng>,
+    //Are we picking fine details right now?
+    in_detail: bool,
+}
+
+thread_local! {
+    //While a seed is set, every rng we hand out is drawn from it instead of the OS.
+    static SEEDED_RNGS: RefCell<SeededRngs> = const {
+        RefCell::new(SeededRngs {
+            structure: None,
+            detail: None,
+            in_detail: false,
+        })
+    };
+}
+
+pub fn get_rng() -> GameRng {
+    SEEDED_RNGS.with(|seeded| {
+        let seeded = &mut *seeded.borrow_mut();
+        let source = match &mut seeded.detail {
+            Some(detail) if seeded.in_detail => Some(detail),
+            _ => seeded.structure.as_mut(),
+        };
+        match source {
+            Some(source) => GameRng::from_rng(source),
+            None => unseeded_rng(),
+        }
+    })
+}
+
+#[cfg(feature = "entropy")]
+fn unseeded_rng() -> GameRng {
+    //Same as thread_rng did: an OS that can't hand out a seed is beyond saving.
+    let mut seed = [0_u8; 32];
+    getrandom::getrandom(&mut seed).expect("the OS has no entropy to draw a seed from");
+    GameRng::from_seed(seed)
+}
+
+#[cfg(not(feature = "entropy"))]
+fn unseeded_rng() -> GameRng {
+    /*
+    Built without the entropy feature there is nowhere to get a random seed
+    from. Everything that would need one is left out of such builds, and
+    builders refuse to grow fish without a seed, so this is only reached by
+    calling get_rng directly; rather than panic, it draws from seed 0, the
+    same fish every time. The seeded rngs are the same PCG64 either way, so
+    a seed grows exactly the same fish with or without the feature.
+    */
+    GameRng::seed_from_u64(0)
+}
+
+pub fn random<T>() -> T
+where
+    Standard: Distribution<T>,
+{
+    get_rng().gen()
+}
+
+pub fn with_seed<T, F: FnOnce() -> T>(seed: u64, f: F) -> T {
+    /*
+    Run f with every random decision on this thread drawn from the seed.
+    Everything that asks get_rng for its randomness gets a fresh rng split off
+    a single seeded one, in the order they ask, so running the same code with
+    the same seed makes exactly the same choices.
+    Seeds nest; the previous state comes back once f is done, even if it panics.
+    */
+    with_seeded_rngs(
+        SeededRngs {
+            structure: Some(GameRng::seed_from_u64(seed)),
+            detail: None,
+            in_detail: false,
+        },
+        f,
+    )
+}
+
+pub fn with_seeds<T, F: FnOnce() -> T>(structure_seed: u64, detail_seed: u64, f: F) -> T {
+    /*
+    Like with_seed, but anything picked inside in_detail is drawn from a
+    separate seed. Changing only the detail seed keeps every big decision
+    the same and just varies the fine details.
+    */
+    with_seeded_rngs(
+        SeededRngs {
+            structure: Some(GameRng::seed_from_u64(structure_seed)),
+            detail: Some(GameRng::seed_from_u64(detail_seed)),
+            in_detail: false,
+        },
+        f,
+    )
+}
+
+pub fn in_detail<T, F: FnOnce() -> T>(f: F) -> T {
+    //Mark every random decision made by f as a fine detail.
+    let previous =
+        SEEDED_RNGS.with(|seeded| std::mem::replace(&mut seeded.borrow_mut().in_detail, true));
+    let _restore = RestoreDetail(previous);
+    f()
+}
+
+fn with_seeded_rngs<T, F: FnOnce() -> T>(rngs: SeededRngs, f: F) -> T {
+    let previous = SEEDED_RNGS.with(|seeded| seeded.replace(rngs));
+    let _restore = RestoreRngs(Some(previous));
+    f()
+}
+
+/*
+These put the previous state back when they are dropped, so it comes back
+even if f panics; otherwise a panic caught further up would leave every
+later draw on this thread stuck on f's seed.
+*/
+struct RestoreRngs(Option<SeededRngs>);
+impl Drop for RestoreRngs {
+    fn drop(&mut self) {
+        if let Some(previous) = self.0.take() {
+            SEEDED_RNGS.with(|seeded| seeded.replace(previous));
+        }
+    }
+}
+
+struct RestoreDetail(bool);
+impl Drop for RestoreDetail {
+    fn drop(&mut self) {
+        SEEDED_RNGS.with(|seeded| seeded.borrow_mut().in_detail = self.0);
+    }
 }
 
 pub fn maybe() -> bool {
-    get_rng().gen_range(0..2) == 0
+    maybe_with(&mut get_rng())
+}
+
+pub fn maybe_with<R: Rng + ?Sized>(rng: &mut R) -> bool {
+    //Same as maybe, but the coin is tossed with the given rng.
+    rng.gen_range(0..2) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::rng::RngCore;
+
+    #[test]
+    fn seeds_draw_pinned_numbers() {
+        /*
+        The first draws from a fixed seed, written down once. If these ever
+        change, every seed grows a different fish than it used to.
+        */
+        let (first, second, range) = with_seed(42, || {
+            let mut rng = get_rng();
+            let mut next = get_rng();
+            (rng.next_u64(), next.next_u64(), rng.gen_range(0..1000_u64))
+        });
+        assert_eq!((first, second, range), (0x250023a3d0c195f9, 0x0ae163e08abb1765, 660));
+        //And one straight from the generator, without splitting an rng off it.
+        assert_eq!(GameRng::seed_from_u64(42).next_u64(), 0x39fcb970a3001809);
+    }
+
+    #[test]
+    fn panics_leave_the_seeds_as_they_were() {
+        let state = || SEEDED_RNGS.with(|seeded| {
+            let seeded = seeded.borrow();
+            (seeded.structure.is_some(), seeded.detail.is_some(), seeded.in_detail)
+        });
+        let before = state();
+        let caught = std::panic::catch_unwind(|| with_seeds(1, 2, || in_detail(|| panic!("f"))));
+        assert!(caught.is_err());
+        assert_eq!(state(), before);
+        //Nested ones come back to the level they were at, too.
+        with_seed(3, || {
+            let inside = state();
+            assert!(std::panic::catch_unwind(|| in_detail(|| panic!("f"))).is_err());
+            assert!(std::panic::catch_unwind(|| with_seed(4, || panic!("f"))).is_err());
+            assert_eq!(state(), inside);
+            assert_eq!(get_rng().next_u64(), with_seed(3, || get_rng().next_u64()));
+        });
+    }
 }
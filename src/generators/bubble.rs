@@ -21,7 +21,7 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 */
 
 use super::super::game;
-use rand::{
+use super::super::rng::{
     distributions::{Distribution, Standard},
     Rng,
 };
@@ -40,20 +40,22 @@ impl Range {
     }
 }
 impl Range {
-    fn random(min_range: std::ops::Range<f64>, max_range: std::ops::Range<f64>) -> Range {
-        let mut rng = game::get_rng();
+    fn random<R: Rng + ?Sized>(
+        rng: &mut R, min_range: std::ops::Range<f64>, max_range: std::ops::Range<f64>
+    ) -> Range {
         Range::new(rng.gen_range(min_range), rng.gen_range(max_range))
     }
-    fn sample(&self) -> f64 {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
         if self.min != self.max {
-            let mut rng = game::get_rng();
             return rng.gen_range(self.min..self.max);
         }
         self.min
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "BubbleData"))]
 pub struct Bubble {
     //by what factor should we shrink the influence of this bubble?
     scale: f64,
@@ -64,43 +66,94 @@ pub struct Bubble {
     //coordinates for the origin of the bubble
     origin: super::GeneratorPoint,
 }
+impl Default for Bubble {
+    fn default() -> Self {
+        //A small round bubble in the middle of the tile.
+        Bubble {
+            scale: 0.05,
+            squish: 1.0,
+            angle: 0.0,
+            origin: super::GeneratorPoint::new(0.5, 0.5),
+        }
+    }
+}
 impl Bubble {
-    fn random(scale: &Range, squish: &Range, angle: &Range) -> Self {
-        let scale = scale.sample();
-        let origin: super::GeneratorPoint = rand::random();
+    const MIN_SCALE: f64 = 1.0e-6;
+    const MIN_SQUISH: f64 = 1.0e-6;
+
+    fn new(scale: f64, squish: f64, angle: f64, origin: super::GeneratorPoint) -> Self {
+        /*
+        The bubble divides by both its scale and its squish, so keep them a
+        hair away from zero; such a bubble is far too small to see anyway.
+        The scale range starts at zero, and a zero-width range there samples
+        exactly zero. The squish ranges never reach zero, but a bubble read
+        in from elsewhere might. A negative squish reverses the effect (see
+        get_squished_bubble_value), so only its size is floored and its sign
+        is kept; a squish of exactly zero counts as positive.
+        */
         Bubble {
-            scale,
-            squish: squish.sample(),
-            angle: angle.sample(),
+            scale: f64::max(scale, Bubble::MIN_SCALE),
+            squish: squish.signum() * squish.abs().max(Bubble::MIN_SQUISH),
+            angle,
             origin,
         }
     }
+
+    fn random<R: Rng + ?Sized>(rng: &mut R, scale: &Range, squish: &Range, angle: &Range) -> Self {
+        let scale = scale.sample(rng);
+        let origin: super::GeneratorPoint = rng.gen();
+        let squish = squish.sample(rng);
+        Bubble::new(scale, squish, angle.sample(rng), origin)
+    }
+}
+
+//A bubble as it is written out, before its scale and squish are kept above zero.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct BubbleData {
+    scale: f64,
+    squish: f64,
+    angle: f64,
+    origin: super::GeneratorPoint,
+}
+
+#[cfg(feature = "serde")]
+impl From<BubbleData> for Bubble {
+    fn from(data: BubbleData) -> Self {
+        Bubble::new(data.scale, data.squish, data.angle, data.origin)
+    }
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BubbleParams {
     bubbles: Vec<Bubble>,
 }
 impl BubbleParams {
     const MAX_BUBBLES: usize = 32;
     const MIN_BUBBLES: usize = BubbleParams::MAX_BUBBLES / 4;
-}
-impl Default for BubbleParams {
-    fn default() -> Self {
-        BubbleParams {
-            bubbles: (0..BubbleParams::MIN_BUBBLES)
-                .map(|_| Default::default())
-                .collect(),
+
+    #[cfg(feature = "entropy")]
+    pub fn new(count: usize) -> Result<Self, String> {
+        //Same as a random BubbleParams, but with exactly this many bubbles.
+        BubbleParams::new_with(count, &mut game::get_rng())
+    }
+
+    pub fn new_with<R: Rng + ?Sized>(count: usize, rng: &mut R) -> Result<Self, String> {
+        //Same as new, but the bubbles are drawn from the given rng.
+        if count < 1 {
+            return Err("must be 1 <= count".to_string());
         }
+        Ok(BubbleParams::with_count(rng, count))
     }
-}
-impl Distribution<BubbleParams> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> BubbleParams {
-        let scale = Range::random(0.0..0.2, 0.0..0.2);
+
+    fn with_count<R: Rng + ?Sized>(rng: &mut R, count: usize) -> Self {
+        //Every choice is drawn from the one rng, so the same rng state gives the same bubbles.
+        let scale = Range::random(rng, 0.0..0.2, 0.0..0.2);
         let squish = Range::new(
-            if game::maybe() {
+            if game::maybe_with(rng) {
                 let val = rng.gen_range(1.0..4.0);
-                if game::maybe() {
+                if game::maybe_with(rng) {
                     val
                 } else {
                     1.0 / val
@@ -108,9 +161,9 @@ impl Distribution<BubbleParams> for Standard {
             } else {
                 1.0
             },
-            if game::maybe() {
+            if game::maybe_with(rng) {
                 let val = rng.gen_range(1.0..4.0);
-                if game::maybe() {
+                if game::maybe_with(rng) {
                     val
                 } else {
                     1.0 / val
@@ -120,17 +173,61 @@ impl Distribution<BubbleParams> for Standard {
             },
         );
         let angle = Range::random(
+            rng,
             0.0..std::f64::consts::PI / 2.0,
             0.0..std::f64::consts::PI / 2.0,
         );
-        let bubbles = (0..rng.gen_range(BubbleParams::MIN_BUBBLES..BubbleParams::MAX_BUBBLES))
-            .map(|_| Bubble::random(&scale, &squish, &angle))
+        let bubbles = (0..count)
+            .map(|_| Bubble::random(rng, &scale, &squish, &angle))
             .collect();
         BubbleParams {
             bubbles,
         }
     }
 }
+impl Default for BubbleParams {
+    fn default() -> Self {
+        BubbleParams {
+            /*
+            Scatter the bubbles over the tile in a fixed pattern, one per
+            row and column, so they don't all sit on top of each other.
+            */
+            bubbles: (0..BubbleParams::MIN_BUBBLES)
+                .map(|i| {
+                    let count = BubbleParams::MIN_BUBBLES as f64;
+                    let row = (i * 3) % BubbleParams::MIN_BUBBLES;
+                    Bubble {
+                        origin: super::GeneratorPoint::new(
+                            (i as f64 + 0.5) / count,
+                            (row as f64 + 0.5) / count,
+                        ),
+                        ..Default::default()
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+impl Distribution<BubbleParams> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> BubbleParams {
+        let count = rng.gen_range(
+            BubbleParams::MIN_BUBBLES as u64..=BubbleParams::MAX_BUBBLES as u64
+        ) as usize;
+        BubbleParams::with_count(rng, count)
+    }
+}
+
+impl super::Generator for BubbleParams {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        generate(super::GeneratorPoint::new(x, y), self)
+    }
+    fn is_seamless(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Bubble).is_seamless
+    }
+    fn is_anti_aliased(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Bubble).is_anti_aliased
+    }
+}
 
 pub fn generate(pixel: super::GeneratorPoint, params: &BubbleParams) -> f64 {
     /*
@@ -172,7 +269,8 @@ pub fn generate(pixel: super::GeneratorPoint, params: &BubbleParams) -> f64 {
             * pixel.y, //bottom right
     ]
     .iter()
-    .fold(f64::NAN, |m, v| v.max(m))
+    .filter(|v| !v.is_nan())
+    .fold(f64::NEG_INFINITY, |m, v| v.max(m))
 }
 
 fn get_all_bubbles_value(pixel: super::GeneratorPoint, params: &BubbleParams) -> f64 {
@@ -180,12 +278,14 @@ fn get_all_bubbles_value(pixel: super::GeneratorPoint, params: &BubbleParams) ->
     Get the biggest lump we can from this array of bubbles.
     We just scan through the list, compare the point with each bubble,
     and return the best match we can find.
+    A bubble that can't produce a number at this point is simply left out.
     */
     params
         .bubbles
         .iter()
         .map(|bubble| get_one_bubble_value(pixel, bubble))
-        .fold(f64::NAN, f64::max)
+        .filter(|v| !v.is_nan())
+        .fold(f64::NEG_INFINITY, f64::max)
 }
 
 fn get_one_bubble_value(pixel: super::GeneratorPoint, params: &Bubble) -> f64 {
@@ -196,15 +296,13 @@ fn get_one_bubble_value(pixel: super::GeneratorPoint, params: &Bubble) -> f64 {
     //Move the coordinates into bubble-relative coordinates.
     let x = pixel.x - params.origin.x;
     let y = pixel.y - params.origin.y;
-    //Calculate the distance from the new origin to this point.
-    let hypotenuse = x.hypot(y);
     /*
-    Draw a line from the origin to this point. Get the angle this line
-    forms with the horizontal. Then add the amount this bubble is rotated.
+    Calculate the distance from the new origin to this point, and draw a
+    line from the origin to this point. Get the angle this line forms with
+    the horizontal. Then add the amount this bubble is rotated.
     */
-    let hypangle = (y / x).atan() + params.angle
-        //The next line is magic. I don't quite understand it.
-        + if x < 0.0 {std::f64::consts::PI} else {0.0};
+    let (hypangle, hypotenuse) = super::angle_and_dist(x, y);
+    let hypangle = hypangle + params.angle;
     //We have the angle and the hypotenuse. Take the sine and cosine to get
     //the new horizontal and vertical distances in the new coordinate system.
     let transverse = hypangle.cos() * hypotenuse + params.origin.x;
@@ -230,3 +328,119 @@ fn get_squished_bubble_value(transverse: f64, distance: f64, params: &Bubble) ->
     let hypotenuse = (transverse - params.origin.x).hypot(distance - params.origin.y);
     1.0 - hypotenuse * hypotenuse / params.scale
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{game, types};
+    use super::super::{generate_with_options, GeneratorParams, GeneratorPoint, Generators};
+    use super::*;
+    use super::super::super::rng::SeedableRng;
+
+    #[test]
+    fn zero_scale_bubbles_draw_finite_pixels() {
+        //Built by hand, so not even the floor Bubble::random applies keeps them off zero.
+        let params = GeneratorParams {
+            bubble: BubbleParams {
+                bubbles: (0..4).map(|i| Bubble {
+                    scale: 0.0,
+                    origin: GeneratorPoint::new(i as f64 / 4.0, 0.5),
+                    ..Default::default()
+                }).collect(),
+            },
+            ..Default::default()
+        };
+        let map = generate_with_options(
+            types::Area::new(16, 16), &Generators::Bubble, &params, &Default::default(),
+            &mut game::GameRng::seed_from_u64(0)
+        );
+        assert!(map.iter().flatten().all(|value| value.is_finite()), "{:?}", map);
+    }
+
+    #[test]
+    fn flattened_bubbles_stay_finite() {
+        let origin = GeneratorPoint::new(0.5, 0.5);
+        let params = BubbleParams {
+            bubbles: vec![Bubble::new(0.05, 0.0, 0.0, origin)],
+        };
+        for x in 0..=16 {
+            for y in 0..=16 {
+                let point = GeneratorPoint::new(x as f64 / 16.0, y as f64 / 16.0);
+                let value = generate(point, &params);
+                assert!(value.is_finite() && value <= 1.0, "{} at {:?}", value, point);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn bubbles_read_in_are_kept_above_zero() {
+        let json = r#"{"scale":0.0,"squish":0.0,"angle":0.0,"origin":{"x":0.5,"y":0.5}}"#;
+        let bubble: Bubble = serde_json::from_str(json).unwrap();
+        assert_eq!((bubble.scale, bubble.squish), (Bubble::MIN_SCALE, Bubble::MIN_SQUISH));
+    }
+
+    #[test]
+    fn bubbles_that_give_nan_are_left_out() {
+        //A bubble with no angle gives NaN everywhere, and a zero-scale one on its own origin.
+        let broken = vec![
+            Bubble {angle: f64::NAN, ..Default::default()},
+            Bubble {scale: 0.0, origin: GeneratorPoint::new(0.25, 0.25), ..Default::default()},
+        ];
+        let sound = || Bubble {origin: GeneratorPoint::new(0.75, 0.5), ..Default::default()};
+        let alone = BubbleParams {bubbles: vec![sound()]};
+        let mixed = BubbleParams {bubbles: broken.into_iter().chain(Some(sound())).collect()};
+        for x in 0..=16 {
+            for y in 0..=16 {
+                let point = GeneratorPoint::new(x as f64 / 16.0, y as f64 / 16.0);
+                let value = generate(point, &mixed);
+                assert!(value.is_finite(), "{} at {:?}", value, point);
+                assert_eq!(value, generate(point, &alone), "at {:?}", point);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_width_ranges_at_zero_draw_finite_pixels() {
+        let mut rng = game::GameRng::seed_from_u64(2);
+        let (nothing, one) = (Range::new(0.0, 0.0), Range::new(1.0, 1.0));
+        assert_eq!(nothing.sample(&mut rng), 0.0);
+        let bubbles: Vec<_> = (0..8).map(|_| Bubble::random(&mut rng, &nothing, &one, &nothing))
+            .collect();
+        assert!(bubbles.iter().all(|bubble| bubble.scale == Bubble::MIN_SCALE));
+        let params = GeneratorParams {bubble: BubbleParams {bubbles}, ..Default::default()};
+        let map = generate_with_options(
+            types::Area::new(16, 16), &Generators::Bubble, &params, &Default::default(), &mut rng
+        );
+        assert!(map.iter().flatten().all(|value| value.is_finite()), "{:?}", map);
+    }
+
+    #[test]
+    fn asking_for_fifty_bubbles_gives_fifty() {
+        //Past the most a random one would have, so the count can't have come from the rng.
+        let mut rng = game::GameRng::seed_from_u64(1);
+        let params = BubbleParams::new_with(50, &mut rng).unwrap();
+        assert_eq!(params.bubbles.len(), 50);
+        assert!(BubbleParams::new_with(0, &mut rng).is_err());
+    }
+
+    #[test]
+    fn the_exact_origin_is_the_peak() {
+        for seed in 0..8 {
+            let params: BubbleParams = game::GameRng::seed_from_u64(seed).gen();
+            for bubble in &params.bubbles {
+                assert_eq!(get_one_bubble_value(bubble.origin, bubble), 1.0, "{:?}", bubble);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_squishes_keep_their_sign() {
+        let origin = GeneratorPoint::new(0.5, 0.5);
+        let squish = |squish| Bubble::new(0.1, squish, 0.0, origin).squish;
+        assert_eq!(squish(-0.5), -0.5);
+        assert_eq!(squish(-1.0e-9), -Bubble::MIN_SQUISH);
+        assert_eq!(squish(0.0), Bubble::MIN_SQUISH);
+        assert_eq!(squish(1.0e-9), Bubble::MIN_SQUISH);
+        assert_eq!(squish(2.0), 2.0);
+    }
+}
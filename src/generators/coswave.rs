@@ -20,23 +20,23 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 
 */
 
-use rand::{
+use super::super::rng::{
     distributions::{Distribution, Standard},
     Rng,
 };
 
 #[derive(Debug)]
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WaveAccelMethods {
     #[default]
-    DEFAULT,
     None,
     Linear,
 }
 
 
 #[derive(Debug)]
-#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CoswaveParams {
     origin: super::GeneratorPoint,
     wave_scale: f64,
@@ -46,12 +46,61 @@ pub struct CoswaveParams {
     pack_method: super::PackMethods,
     accel_method: WaveAccelMethods,
     accel: f64,
+    /*
+    Tile the ripples ourselves, the way the spinflake does, instead of
+    leaving it to the generic corner blend, which bends the rings out of
+    shape near the edges.
+    */
+    seamless: bool,
+}
+impl CoswaveParams {
+    const MIN_SQUISH: f64 = 1.0e-6;
+
+    pub fn set_origin(&mut self, origin: super::GeneratorPoint) {
+        self.origin = origin;
+    }
+    pub fn set_seamless(&mut self, seamless: bool) {
+        self.seamless = seamless;
+    }
+    pub fn is_seamless(&self) -> bool {
+        self.seamless
+    }
+    pub fn set_squish(&mut self, squish: f64) {
+        /*
+        Stretch the rings into ellipses: the distance along the squish angle
+        is multiplied by the squish and the distance across it is divided by
+        it, so a squish of 2.0 makes rings twice as tight along the angle and
+        twice as loose across it.
+        Both distances are squared before they are added up, so the sign of
+        the squish makes no difference to the picture at all: -2.0 draws
+        exactly what 2.0 does. Only its size counts.
+        The distance across is divided by the squish, so its size is kept a
+        hair above zero, where the rings are far too thin to see anyway.
+        */
+        self.squish = squish.signum() * squish.abs().max(CoswaveParams::MIN_SQUISH);
+    }
+}
+impl Default for CoswaveParams {
+    fn default() -> Self {
+        //Plain round ripples spreading out from the middle of the tile.
+        CoswaveParams {
+            origin: super::GeneratorPoint::new(0.5, 0.5),
+            wave_scale: 20.0,
+            squish: 1.0,
+            sqangle: 0.0,
+            distortion: 1.0,
+            pack_method: Default::default(),
+            accel_method: Default::default(),
+            accel: 0.0,
+            seamless: false,
+        }
+    }
 }
 impl Distribution<CoswaveParams> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> CoswaveParams {
         let mut params = CoswaveParams {
-            origin: rand::random(),
-            pack_method: rand::random(),
+            origin: rng.gen(),
+            pack_method: rng.gen(),
             wave_scale: rng.gen_range(0.0..=25.0) + 1.0,
             /*
             We don't like waves that are always perfect circles; they're too
@@ -65,11 +114,16 @@ impl Distribution<CoswaveParams> for Standard {
             */
             sqangle: rng.gen_range(0.0..=std::f64::consts::PI),
             distortion: rng.gen_range(0.0..=1.5) + 0.5,
+            /*
+            The sign is still drawn so that seeds keep growing the same
+            textures, but it has no effect; see set_squish.
+            */
             squish: (rng.gen_range(0.0..=2.0) + 0.5)
                 * if rng.gen_range(0..2) == 0 {1.0} else {-1.0},
             /* fill with default value (set later) */
-            accel_method: WaveAccelMethods::DEFAULT,
+            accel_method: WaveAccelMethods::None,
             accel: 0.0,
+            seamless: false,
         };
 
         /*
@@ -94,9 +148,12 @@ impl Distribution<CoswaveParams> for Standard {
         Packmethods flipsign and truncate effectively double the wavescale,
         because they turn both peaks and valleys into peaks. So we use a lower
         wavescale, then double it with the scaleToFit method to put it in range
-        with the other packmethods.
+        with the other packmethods. The square wave keeps one crest per cycle
+        just like scaleToFit, so it gets the same treatment.
         */
-        if let super::PackMethods::ScaleToFit = params.pack_method {
+        if let super::PackMethods::ScaleToFit | super::PackMethods::SquareToFit(_) =
+            params.pack_method
+        {
             params.wave_scale *= 2.0;
         }
 
@@ -104,13 +161,97 @@ impl Distribution<CoswaveParams> for Standard {
     }
 }
 
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CoswaveStack {
+    /*
+    Several coswaves laid over each other in one texture and averaged, the
+    way a spinflake lays its florets together. Their rings cut across each
+    other wherever the origins and scales differ, making moire-like
+    interference that no single coswave can draw.
+    */
+    waves: Vec<CoswaveParams>,
+}
+impl CoswaveStack {
+    pub fn new(waves: Vec<CoswaveParams>) -> Self {
+        CoswaveStack { waves }
+    }
+    pub fn push(&mut self, wave: CoswaveParams) {
+        self.waves.push(wave);
+    }
+    pub fn is_seamless(&self) -> bool {
+        //The average only tiles if every wave in it does.
+        !self.waves.is_empty() && self.waves.iter().all(|wave| wave.is_seamless())
+    }
+}
+impl Default for CoswaveStack {
+    fn default() -> Self {
+        //A single plain coswave, which draws exactly what that coswave would alone.
+        CoswaveStack::new(vec![Default::default()])
+    }
+}
+impl Distribution<CoswaveStack> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> CoswaveStack {
+        //More than a handful of waves just averages out into flat grey.
+        let count = rng.gen_range(2..=4);
+        CoswaveStack::new((0..count).map(|_| rng.gen()).collect())
+    }
+}
+
+impl super::Generator for CoswaveParams {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        generate(super::GeneratorPoint::new(x, y), self)
+    }
+    fn is_seamless(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Coswave).is_seamless
+            || CoswaveParams::is_seamless(self)
+    }
+    fn is_anti_aliased(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Coswave).is_anti_aliased
+    }
+}
+
+impl super::Generator for CoswaveStack {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        generate_stack(super::GeneratorPoint::new(x, y), self)
+    }
+    fn is_seamless(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::CoswaveStack).is_seamless
+            || CoswaveStack::is_seamless(self)
+    }
+    fn is_anti_aliased(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::CoswaveStack).is_anti_aliased
+    }
+}
+
+pub fn generate_stack(pixel: super::GeneratorPoint, stack: &CoswaveStack) -> f64 {
+    if stack.waves.is_empty() {
+        return 0.0;
+    }
+    stack.waves.iter().map(
+        |wave| generate(pixel, wave)
+    ).sum::<f64>() / (stack.waves.len() as f64)
+}
+
 pub fn generate(pixel: super::GeneratorPoint, params: &CoswaveParams) -> f64 {
+    if params.seamless {
+        return super::tiled_point(pixel.x, pixel.y, |x, y| rawpoint(x, y, params));
+    }
+    rawpoint(pixel.x, pixel.y, params)
+}
+
+fn rawpoint(x: f64, y: f64, params: &CoswaveParams) -> f64 {
     //Rotate the axes of this shape.
-    let x = pixel.x - params.origin.x;
-    let y = pixel.y - params.origin.y;
+    let x = x - params.origin.x;
+    let y = y - params.origin.y;
 
-    let hypangle = ((y / x) * params.distortion).atan() + params.sqangle;
-    let hypotenuse = x.hypot(y);
+    /*
+    The distortion stretches the angle, not the distance: it is the angle
+    of the point with its y scaled, turned by the squish angle.
+    */
+    let (hypangle, _) = super::angle_and_dist(x, y * params.distortion);
+    let hypangle = hypangle + params.sqangle;
+    let (_, hypotenuse) = super::angle_and_dist(x, y);
 
     let x = hypangle.cos() * hypotenuse;
     let y = hypangle.sin() * hypotenuse;
@@ -120,8 +261,107 @@ pub fn generate(pixel: super::GeneratorPoint, params: &CoswaveParams) -> f64 {
     //Scale the wavescale according to our accelerator function.
     let compwavescale = match params.accel_method {
         WaveAccelMethods::None => params.wave_scale,
-        _ => params.wave_scale.powf(hypotenuse * params.accel),
+        WaveAccelMethods::Linear => params.wave_scale.powf(hypotenuse * params.accel),
     };
     let rawcos = super::packed_cos(hypotenuse, compwavescale, &params.pack_method);
     (rawcos + 1.0) / 2.0
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::game;
+    use super::super::{generate_1d_with_rng, GeneratorParams, GeneratorPoint, Generators};
+    use super::super::super::rng::SeedableRng;
+
+    #[test]
+    fn a_stack_of_one_is_just_that_wave() {
+        for seed in 0..8 {
+            let wave = || -> CoswaveParams { game::GameRng::seed_from_u64(seed).gen() };
+            let (plain, stack) = (wave(), CoswaveStack::new(vec![wave()]));
+            for i in 0..16 * 16 {
+                let point = GeneratorPoint::new((i % 16) as f64 / 16.0, (i / 16) as f64 / 16.0);
+                assert_eq!(generate_stack(point, &stack), generate(point, &plain));
+            }
+        }
+    }
+
+    #[test]
+    fn a_long_slow_strip_rises_and_falls_once() {
+        /*
+        Half a wave along the strip. Rolled round to any start, and with its
+        ends wrapped into each other, it still only turns at its top and at
+        its bottom, never in between.
+        */
+        let params = GeneratorParams {
+            coswave: CoswaveParams {
+                origin: GeneratorPoint::new(0.0, 0.0),
+                wave_scale: std::f64::consts::PI,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for seed in 0..4 {
+            let mut rng = game::GameRng::seed_from_u64(seed);
+            let strip = generate_1d_with_rng(256, &Generators::Coswave, &params, &mut rng);
+            assert_eq!(strip.len(), 256);
+            let rising: Vec<_> = (0..256).map(|i| strip[(i + 1) % 256] >= strip[i]).collect();
+            let turns = (0..256).filter(|&i| rising[(i + 1) % 256] != rising[i]).count();
+            assert!(turns <= 2, "{} turns in {:?}", turns, strip);
+        }
+    }
+
+    #[test]
+    fn squishes_of_either_sign_draw_the_same_rings() {
+        for seed in 0..8 {
+            let mut wave: CoswaveParams = game::GameRng::seed_from_u64(seed).gen();
+            let mut flipped: CoswaveParams = game::GameRng::seed_from_u64(seed).gen();
+            wave.set_squish(1.5);
+            flipped.set_squish(-1.5);
+            for i in 0..16 * 16 {
+                let point = GeneratorPoint::new((i % 16) as f64 / 16.0, (i / 16) as f64 / 16.0);
+                assert_eq!(generate(point, &wave), generate(point, &flipped));
+            }
+        }
+    }
+
+    #[test]
+    fn zero_squishes_are_kept_above_zero() {
+        let mut wave = CoswaveParams::default();
+        for &squish in &[0.0, -0.0] {
+            wave.set_squish(squish);
+            assert_eq!(wave.squish.abs(), CoswaveParams::MIN_SQUISH);
+            assert!(generate(GeneratorPoint::new(0.25, 0.75), &wave).is_finite());
+        }
+    }
+
+    #[test]
+    fn seamless_waves_meet_themselves_at_the_edges() {
+        use super::super::Generator;
+        for seed in 0..8 {
+            let mut wave: CoswaveParams = game::GameRng::seed_from_u64(seed).gen();
+            let seam = |wave: &CoswaveParams| (0..=16).map(|i| i as f64 / 16.0).map(
+                |t| (
+                    (generate(GeneratorPoint::new(1.0, t), wave)
+                        - generate(GeneratorPoint::new(0.0, t), wave)).abs(),
+                    (generate(GeneratorPoint::new(t, 1.0), wave)
+                        - generate(GeneratorPoint::new(t, 0.0), wave)).abs()
+                )
+            ).fold(0.0, |worst: f64, (across, down)| worst.max(across).max(down));
+            let plain = seam(&wave);
+            wave.set_seamless(true);
+            assert!(Generator::is_seamless(&wave));
+            assert!(seam(&wave) < 1e-12, "{} apart at the edges", seam(&wave));
+            assert!(plain > 1e-3, "seed {} already met itself", seed);
+        }
+    }
+
+    #[test]
+    fn the_exact_origin_is_finite() {
+        for seed in 0..8 {
+            let wave: CoswaveParams = game::GameRng::seed_from_u64(seed).gen();
+            let value = generate(wave.origin, &wave);
+            assert!(value.is_finite(), "seed {}: {}", seed, value);
+        }
+    }
+}
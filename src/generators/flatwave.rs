@@ -20,20 +20,20 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 
 */
 
-use rand::{
+use super::super::rng::{
     distributions::{Distribution, Standard},
     Rng,
 };
 
 #[derive(Debug)]
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterferenceMethods {
-    #[default]
-    DEFAULT,
     MostExtreme,
     LeastExtreme,
     Max,
     Min,
+    #[default]
     Average,
 }
 impl Distribution<InterferenceMethods> for Standard {
@@ -50,10 +50,10 @@ impl Distribution<InterferenceMethods> for Standard {
 
 #[derive(Debug)]
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AccelMethods {
-    #[default]
-    DEFAULT,
     Enabled,
+    #[default]
     Disabled,
 }
 impl Distribution<AccelMethods> for Standard {
@@ -67,6 +67,7 @@ impl Distribution<AccelMethods> for Standard {
 
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Accel {
     scale: f64,
     amp: f64,
@@ -78,8 +79,8 @@ impl Distribution<Accel> for Standard {
         Accel {
             scale: rng.gen_range(2.0..30.0),
             amp: rng.gen_range(0.0..0.1),
-            pack: rand::random(),
-            accel: rand::random(),
+            pack: rng.gen(),
+            accel: rng.gen(),
         }
     }
 }
@@ -89,24 +90,37 @@ A wave is a curve on a line.
 Each wave may have different scaling
 and display packing options.
 */
-#[derive(Debug, Default)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Wave {
     scale: f64,
     pack_method: super::PackMethods,
     accel: Accel,
 }
+impl Default for Wave {
+    fn default() -> Self {
+        //Plain scaled cosine bars, doubled up just like sampled ones.
+        Wave {
+            scale: 20.0,
+            pack_method: Default::default(),
+            accel: Default::default(),
+        }
+    }
+}
 impl Distribution<Wave> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Wave {
-        let pack_method: super::PackMethods = rand::random();
+        let pack_method: super::PackMethods = rng.gen();
         Wave {
             scale: rng.gen_range(2.0..30.0)
-                * if let super::PackMethods::ScaleToFit = pack_method {
+                * if let super::PackMethods::ScaleToFit | super::PackMethods::SquareToFit(_) =
+                    pack_method
+                {
                     2.0
                 } else {
                     1.0
                 },
             pack_method,
-            accel: rand::random(),
+            accel: rng.gen(),
         }
     }
 }
@@ -117,6 +131,7 @@ A wavepacket has an origin and an angle. All waves
 in the packet are calculated relative to that line.
 */
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WavePacket {
     origin: super::GeneratorPoint,
     angle: f64,
@@ -125,9 +140,9 @@ pub struct WavePacket {
 impl Distribution<WavePacket> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> WavePacket {
         WavePacket {
-            origin: rand::random(),
+            origin: rng.gen(),
             angle: rng.gen_range(0.0..std::f64::consts::PI),
-            wave: rand::random(),
+            wave: rng.gen(),
         }
     }
 }
@@ -139,6 +154,7 @@ wavepackets and a description of the way to
 interfere them with each other.
 */
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FlatwaveParams {
     interference_method: InterferenceMethods,
     pub packets: Vec<WavePacket>,
@@ -157,14 +173,26 @@ impl Default for FlatwaveParams {
 impl Distribution<FlatwaveParams> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> FlatwaveParams {
         FlatwaveParams {
-            interference_method: rand::random(),
+            interference_method: rng.gen(),
             packets: (0..=rng.gen_range(1..=FlatwaveParams::MAX_WAVE_PACKETS))
-                .map(|_| rand::random())
+                .map(|_| rng.gen())
                 .collect(),
         }
     }
 }
 
+impl super::Generator for FlatwaveParams {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        generate(super::GeneratorPoint::new(x, y), self)
+    }
+    fn is_seamless(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Flatwave).is_seamless
+    }
+    fn is_anti_aliased(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Flatwave).is_anti_aliased
+    }
+}
+
 pub fn generate(pixel: super::GeneratorPoint, params: &FlatwaveParams) -> f64 {
     /*
     Turn the angle from the origin to this point into a right triangle.
@@ -174,7 +202,9 @@ pub fn generate(pixel: super::GeneratorPoint, params: &FlatwaveParams) -> f64 {
     let mut out = match params.interference_method {
         InterferenceMethods::Min => 1.0_f64,
         InterferenceMethods::MostExtreme => 0.5_f64,
-        _ => 0.0_f64,
+        InterferenceMethods::LeastExtreme
+        | InterferenceMethods::Max
+        | InterferenceMethods::Average => 0.0_f64,
     };
     for packet in &params.packets {
         let layer = calc_wave_packet(pixel, packet);
@@ -207,8 +237,6 @@ pub fn generate(pixel: super::GeneratorPoint, params: &FlatwaveParams) -> f64 {
                 InterferenceMethods::Min => f64::min(layer, out),
                 //Sum all the values up and compute the average at the end.
                 InterferenceMethods::Average => out + layer,
-                //Beats me what to do with this case. It should never happen.
-                _ => layer,
             }
         } else {
             layer
@@ -232,10 +260,9 @@ fn calc_wave_packet(pixel: super::GeneratorPoint, params: &WavePacket) -> f64 {
     //Re-centre the point on our wave's origin.
     let x = pixel.x - params.origin.x;
     let y = pixel.y - params.origin.y;
-    //Now figure the length from the origin to this point.
-    let hypotenuse = x.hypot(y);
-    //Find the angle of the line from this point to the origin.
-    let hypangle = (y / x).atan() + params.angle + if x < 0.0 { std::f64::consts::PI } else { 0.0 };
+    //Now figure the length from the origin to this point, and the angle of the line between them.
+    let (hypangle, hypotenuse) = super::angle_and_dist(x, y);
+    let hypangle = hypangle + params.angle;
     //Using the angle and the hypotenuse, we can figure out the individual legs.
     let transverse = hypangle.cos() * hypotenuse;
     let distance = hypangle.sin() * hypotenuse;
@@ -256,9 +283,25 @@ fn calc_wave(distance: f64, transverse: f64, params: &Wave) -> f64 {
                     super::packed_cos(transverse, params.accel.scale, &params.accel.pack)
                         * params.accel.amp
                 },
-                _ => {0.0}
+                AccelMethods::Disabled => {0.0}
             },
         params.scale,
         &params.pack_method,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::game;
+    use super::*;
+    use super::super::super::rng::SeedableRng;
+
+    #[test]
+    fn the_exact_origin_is_finite() {
+        for seed in 0..8 {
+            let packet: WavePacket = game::GameRng::seed_from_u64(seed).gen();
+            let value = calc_wave_packet(packet.origin, &packet);
+            assert!(value.is_finite(), "seed {}: {}", seed, value);
+        }
+    }
+}
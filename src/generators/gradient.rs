@@ -0,0 +1,170 @@
+/*
+
+Copyright ©2021 Amane Katagiri
+Copyright ©1999 Mars Saxman
+All Rights Reserved
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, write to the Free Software
+Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
+
+*/
+
+use super::super::rng::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
+
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GradientKinds {
+    //A straight ramp across the tile, rising in the direction of the angle.
+    #[default]
+    Linear,
+    //Rings rising outwards from the origin.
+    Radial,
+}
+impl Distribution<GradientKinds> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> GradientKinds {
+        match rng.gen_range(0..=1) {
+            0 => GradientKinds::Linear,
+            _ => GradientKinds::Radial,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GradientParams {
+    //Which way a linear ramp rises, in radians from the x axis towards increasing y.
+    pub angle: f64,
+    pub kind: GradientKinds,
+    //Where a radial ramp starts, and the point a linear ramp turns around.
+    pub origin: super::GeneratorPoint,
+}
+impl GradientParams {
+    pub fn set_origin(&mut self, origin: super::GeneratorPoint) {
+        self.origin = origin;
+    }
+    pub fn is_seamless(&self) -> bool {
+        /*
+        Rings around the middle of the tile meet themselves across every
+        edge. A ramp never does: one edge is dark and the other one light.
+        */
+        self.kind == GradientKinds::Radial && self.origin.x() == 0.5 && self.origin.y() == 0.5
+    }
+}
+impl Default for GradientParams {
+    fn default() -> Self {
+        //A ramp running left to right.
+        GradientParams {
+            angle: 0.0,
+            kind: GradientKinds::Linear,
+            origin: super::GeneratorPoint::new(0.5, 0.5),
+        }
+    }
+}
+impl Distribution<GradientParams> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> GradientParams {
+        GradientParams {
+            angle: rng.gen_range(0.0..(2.0 * std::f64::consts::PI)),
+            kind: rng.gen(),
+            origin: rng.gen(),
+        }
+    }
+}
+
+impl super::Generator for GradientParams {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        generate(super::GeneratorPoint::new(x, y), self)
+    }
+    fn is_seamless(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Gradient).is_seamless
+            || GradientParams::is_seamless(self)
+    }
+    fn is_anti_aliased(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Gradient).is_anti_aliased
+    }
+    fn wraps_edges(&self) -> bool {
+        /*
+        A ramp doesn't tile, but it is meant to run from edge to edge, and
+        blending its far end back in would leave no ramp at all. So its
+        edges are left alone, seamless or not.
+        */
+        false
+    }
+}
+
+pub fn generate(pixel: super::GeneratorPoint, params: &GradientParams) -> f64 {
+    /*
+    Both kinds of ramp are scaled so that they run from 0 to 1 across the
+    tile itself: a linear ramp is 0 at the corner furthest against the angle
+    and 1 at the corner furthest along it, and a radial ramp is 0 at its
+    origin and 1 at the corner furthest from it.
+    */
+    let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)];
+    let (x, y) = (pixel.x() - params.origin.x(), pixel.y() - params.origin.y());
+    match params.kind {
+        GradientKinds::Linear => {
+            let (cos, sin) = (params.angle.cos(), params.angle.sin());
+            let along = |x: f64, y: f64| x * cos + y * sin;
+            let reach = corners.iter().map(
+                |(cx, cy)| along(cx - params.origin.x(), cy - params.origin.y())
+            );
+            let low = reach.clone().fold(f64::INFINITY, f64::min);
+            let high = reach.fold(f64::NEG_INFINITY, f64::max);
+            (along(x, y) - low) / (high - low)
+        },
+        GradientKinds::Radial => {
+            let reach = corners.iter().map(
+                |(cx, cy)| (cx - params.origin.x()).hypot(cy - params.origin.y())
+            ).fold(0.0, f64::max);
+            if reach > 0.0 {x.hypot(y) / reach} else {0.0}
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{game, types};
+    use super::super::{generate_with_options, Generator, GeneratorParams, Generators};
+    use super::{GradientKinds, GradientParams};
+    use super::super::super::rng::SeedableRng;
+
+    #[test]
+    fn linear_ramps_rise_along_every_row() {
+        //The default ramp runs left to right, and a gradient is never rolled.
+        let map = generate_with_options(
+            types::Area::new(32, 8), &Generators::Gradient, &GeneratorParams::default(),
+            &Default::default(), &mut game::GameRng::seed_from_u64(0)
+        );
+        for row in &map {
+            assert!(row.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", row);
+            assert_eq!(row[0], 0.0);
+        }
+    }
+
+    #[test]
+    fn only_centred_rings_are_seamless_and_no_gradient_wraps() {
+        let ramp = GradientParams::default();
+        let rings = GradientParams {
+            kind: GradientKinds::Radial,
+            ..Default::default()
+        };
+        assert!(!Generator::is_seamless(&ramp));
+        assert!(Generator::is_seamless(&rings));
+        assert!(!ramp.wraps_edges() && !rings.wraps_edges());
+    }
+}
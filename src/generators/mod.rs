@@ -25,18 +25,25 @@ pub mod spinflake;
 pub mod flatwave;
 pub mod rangefrac;
 pub mod bubble;
+pub mod gradient;
+pub mod pixelgrid;
 pub mod test;
 
+#[cfg(feature = "entropy")]
+use super::game;
 use super::types;
 
-use rand::{
+use super::rng::{
     distributions::{Distribution, Standard},
     Rng,
 };
 
 #[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Generators {
-    DEFAULT,
     Test,
     //Our first one is the workhorse Coswave. It can do anything.
     Coswave,
@@ -48,6 +55,90 @@ pub enum Generators {
     Flatwave,
     //Bubble generator, which creates lumpy, curved turbulences.
     Bubble,
+    //A plain linear or radial ramp, for fades rather than texture.
+    Gradient,
+    //Several coswaves averaged together, for interference within one layer.
+    CoswaveStack,
+    //One-pixel grid lines, drawn in whole pixels rather than generator space.
+    PixelGrid,
+}
+impl Generators {
+    pub fn all() -> Vec<Generators> {
+        //Every generator that can be picked for a layer.
+        vec![
+            Generators::Coswave,
+            Generators::Spinflake,
+            Generators::Rangefrac,
+            Generators::Flatwave,
+            Generators::Bubble,
+        ]
+    }
+    pub fn every() -> Vec<Generators> {
+        //Every generator there is, including the ones never picked for a layer.
+        vec![
+            Generators::Test,
+            Generators::Coswave,
+            Generators::Spinflake,
+            Generators::Rangefrac,
+            Generators::Flatwave,
+            Generators::Bubble,
+            Generators::Gradient,
+            Generators::CoswaveStack,
+            Generators::PixelGrid,
+        ]
+    }
+    pub fn with_params<'a>(&self, params: &'a GeneratorParams) -> &'a dyn Generator {
+        //This generator, with its own settings picked out of params.
+        match self {
+            Generators::Coswave => &params.coswave,
+            Generators::Spinflake => &params.spinflake,
+            Generators::Rangefrac => &params.rangefrac,
+            Generators::Flatwave => &params.flatwave,
+            Generators::Bubble => &params.bubble,
+            Generators::Gradient => &params.gradient,
+            Generators::CoswaveStack => &params.coswave_stack,
+            Generators::PixelGrid => &params.pixelgrid,
+            Generators::Test => &TestPattern,
+        }
+    }
+    pub fn info(&self) -> GeneratorInfo {
+        let property = GeneratorProperty::get(self);
+        GeneratorInfo {
+            name: self.name(),
+            description: match self {
+                Generators::Test => "Smooth exponential falloff, for checking the pipeline.",
+                Generators::Coswave => "Rippling cosine waves spreading out from a point.",
+                Generators::Spinflake => "Spiky, twirling flower shapes around a point.",
+                Generators::Rangefrac => "Mountainous, organic rough fractal texture.",
+                Generators::Flatwave => "Interfering straight-line waves.",
+                Generators::Bubble => "Lumpy, curved turbulence from overlapping bubbles.",
+                Generators::Gradient => "A plain linear or radial ramp.",
+                Generators::CoswaveStack => "Several coswaves interfering with each other.",
+                Generators::PixelGrid => "Grid lines exactly one pixel wide.",
+            },
+            is_anti_aliased: property.is_anti_aliased,
+            is_seamless: property.is_seamless,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Generators::Test => "test",
+            Generators::Coswave => "coswave",
+            Generators::Spinflake => "spinflake",
+            Generators::Rangefrac => "rangefrac",
+            Generators::Flatwave => "flatwave",
+            Generators::Bubble => "bubble",
+            Generators::Gradient => "gradient",
+            Generators::CoswaveStack => "coswave-stack",
+            Generators::PixelGrid => "pixel-grid",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Generators> {
+        //Names are matched case-insensitively, so "Coswave" finds the coswave too.
+        Generators::every()
+            .into_iter()
+            .find(|generator| generator.name().eq_ignore_ascii_case(name))
+    }
 }
 impl Distribution<Generators> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Generators {
@@ -61,6 +152,55 @@ impl Distribution<Generators> for Standard {
     }
 }
 
+/*
+What a generator can do, for anyone who wants to show or pick generators
+without knowing about each one in advance.
+*/
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct GeneratorInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    //The generator smooths its own edges; we don't supersample it.
+    pub is_anti_aliased: bool,
+    //The generator tiles on its own; we don't blend its edges.
+    pub is_seamless: bool,
+}
+
+/*
+Anything that can draw a texture: given a point in generator space, give
+back a value from 0 to 1. The params of every built-in generator implement
+it, and generate_with puts any implementor through the same edge wrapping
+and anti-aliasing the built-in generators get, so other crates can add
+generators of their own.
+*/
+pub trait Generator {
+    //The value at x, y. The tile runs 0..1 both ways, but points up to a tile beyond are asked for.
+    fn sample_point(&self, x: f64, y: f64) -> f64;
+    //The generator tiles on its own; we don't blend its edges.
+    fn is_seamless(&self) -> bool;
+    //The generator smooths its own edges; we don't supersample it.
+    fn is_anti_aliased(&self) -> bool;
+    //We blend the far edges of the tile in; by default, whenever it doesn't tile on its own.
+    fn wraps_edges(&self) -> bool {
+        !self.is_seamless()
+    }
+}
+
+//The placeholder pattern drawn by Generators::Test.
+struct TestPattern;
+impl Generator for TestPattern {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        test::generate(x, y)
+    }
+    fn is_seamless(&self) -> bool {
+        GeneratorProperty::get(&Generators::Test).is_seamless
+    }
+    fn is_anti_aliased(&self) -> bool {
+        GeneratorProperty::get(&Generators::Test).is_anti_aliased
+    }
+}
+
 #[derive(Debug)]
 struct GeneratorProperty {
     is_anti_aliased: bool,
@@ -89,58 +229,162 @@ impl GeneratorProperty {
                 is_anti_aliased: true,
                 is_seamless: true,
             },
-            Generators::Test => GeneratorProperty {
+            Generators::Gradient => GeneratorProperty {
+                is_anti_aliased: true,
+                is_seamless: false,
+            },
+            Generators::CoswaveStack => GeneratorProperty {
                 is_anti_aliased: false,
                 is_seamless: false,
             },
-            Generators::DEFAULT => GeneratorProperty {
+            /*
+            Hard one-pixel lines, evenly spread, so the grid tiles but is not
+            smooth. Images draw it through call_absolute_generator, which
+            neither blends its edges nor supersamples it.
+            */
+            Generators::PixelGrid => GeneratorProperty {
+                is_anti_aliased: false,
+                is_seamless: true,
+            },
+            Generators::Test => GeneratorProperty {
                 is_anti_aliased: false,
                 is_seamless: false,
-            }
+            },
         }
     }
 }
 
 #[derive(Debug)]
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GeneratorParams {
     pub coswave: coswave::CoswaveParams,
     pub spinflake: spinflake::SpinflakeParams,
     pub rangefrac: rangefrac::RangefracParams,
     pub flatwave: flatwave::FlatwaveParams,
     pub bubble: bubble::BubbleParams,
+    pub gradient: gradient::GradientParams,
+    pub coswave_stack: coswave::CoswaveStack,
+    pub pixelgrid: pixelgrid::PixelGridParams,
+}
+impl GeneratorParams {
+    pub fn set_origin(&mut self, origin: GeneratorPoint) {
+        /*
+        Move the centre of the radial generators onto this point.
+        The other generators have no single origin, so they are left alone.
+        */
+        self.coswave.set_origin(origin);
+        self.spinflake.set_origin(origin);
+    }
+    pub(crate) fn retain(&mut self, used: &[Generators]) {
+        /*
+        Put every generator that isn't used back to its default settings.
+        That frees what they held, such as a rangefrac's matrix, and the
+        defaults take next to no room when written out.
+        */
+        let unused = |generator| !used.contains(&generator);
+        if unused(Generators::Coswave) {
+            self.coswave = Default::default();
+        }
+        if unused(Generators::Spinflake) {
+            self.spinflake = Default::default();
+        }
+        if unused(Generators::Rangefrac) {
+            self.rangefrac = Default::default();
+        }
+        if unused(Generators::Flatwave) {
+            self.flatwave = Default::default();
+        }
+        if unused(Generators::Bubble) {
+            self.bubble = Default::default();
+        }
+        if unused(Generators::Gradient) {
+            self.gradient = Default::default();
+        }
+        if unused(Generators::CoswaveStack) {
+            self.coswave_stack = Default::default();
+        }
+        if unused(Generators::PixelGrid) {
+            self.pixelgrid = Default::default();
+        }
+    }
 }
 impl Distribution<GeneratorParams> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, _: &mut R) -> GeneratorParams {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> GeneratorParams {
         GeneratorParams {
-            coswave: rand::random(),
-            spinflake: rand::random(),
-            rangefrac: rand::random(),
-            flatwave: rand::random(),
-            bubble: rand::random(),
+            coswave: rng.gen(),
+            spinflake: rng.gen(),
+            rangefrac: rng.gen(),
+            flatwave: rng.gen(),
+            bubble: rng.gen(),
+            gradient: rng.gen(),
+            coswave_stack: rng.gen(),
+            pixelgrid: rng.gen(),
         }
     }
 }
 
 #[derive(Debug)]
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PackMethods {
     #[default]
-    DEFAULT,
     ScaleToFit,
     FlipSignToFit,
     TruncateToFit,
     SlopeToFit,
+    //The value is the duty cycle: the fraction of each wave spent at the top.
+    SquareToFit(f64),
 }
 
 impl Distribution<PackMethods> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> PackMethods {
-        match rng.gen_range(0..=3) {
+        match rng.gen_range(0..=4) {
             0 => PackMethods::ScaleToFit,
             1 => PackMethods::FlipSignToFit,
             2 => PackMethods::TruncateToFit,
-            _ => PackMethods::SlopeToFit,
+            3 => PackMethods::SlopeToFit,
+            _ => PackMethods::SquareToFit(0.5),
+        }
+    }
+}
+pub fn angle_and_dist(x: f64, y: f64) -> (f64, f64) {
+    /*
+    The polar coordinates of a point relative to an origin: the angle of the
+    line from the origin to it, in radians from -PI to PI measured from the
+    x axis towards increasing y, and its length. atan2 looks at the signs of
+    both legs, so it knows the quadrant and never divides by zero; the origin
+    itself has an angle of 0 and a length of 0, so turning it leaves it put.
+    */
+    (y.atan2(x), x.hypot(y))
+}
+pub(crate) fn tiled_point<F: Fn(f64, f64) -> f64>(x: f64, y: f64, raw: F) -> f64 {
+    /*
+    Tile a pattern around a point by hand, for generators that would rather
+    not have their edges blended: the coswave and the spinflake.
+    Over the right half of the tile, fade into the pattern as it would look
+    coming in from the tile to the left, so the right edge meets the left
+    one. The vertical pass does the same from top to bottom. The pattern is
+    only ever faded into another whole copy of itself, so it keeps its shape.
+    */
+    let vtiled = |x: f64| {
+        let point = raw(x, y);
+        if y > 0.5 {
+            let farpoint = raw(x, y - 1.0);
+            let farweight = (y - 0.5) * 2.0;
+            let weight = 1.0 - farweight;
+            return (point * weight) + (farpoint * farweight);
         }
+        point
+    };
+    let val = vtiled(x);
+    if x > 0.5 {
+        let farpoint = vtiled(x - 1.0);
+        let farweight = (x - 0.5) * 2.0;
+        let weight = 1.0 - farweight;
+        return (val * weight) + (farpoint * farweight);
     }
+    val
 }
 pub fn packed_cos(distance: f64, scale: f64, pack_method: &PackMethods) -> f64 {
     /*
@@ -162,7 +406,13 @@ pub fn packed_cos(distance: f64, scale: f64, pack_method: &PackMethods) -> f64 {
         PackMethods::ScaleToFit => (rawcos + 1.0) / 2.0,
         //use only the first half of the cycle. A saw-edge effect.
         PackMethods::SlopeToFit => ((distance * scale % std::f64::consts::PI).cos() + 1.0) / 2.0,
-        _ => 0.5,
+        /*
+        Snap the wave to its top or bottom for hard-edged bars.
+        The wave is high while the angle is within duty * PI of a crest,
+        so a duty cycle of 0.5 is simply the sign of the cosine.
+        */
+        PackMethods::SquareToFit(duty) =>
+            if rawcos >= (duty.clamp(0.0, 1.0) * std::f64::consts::PI).cos() {1.0} else {0.0},
     }
 }
 
@@ -181,10 +431,138 @@ impl RollVector {
             y,
         }
     }
+    fn random_with<R: Rng + ?Sized>(size: types::Area, rng: &mut R) -> Self {
+        RollVector::new(
+            rng.gen_range(0..=size.width as u64) as usize,
+            rng.gen_range(0..=size.height as u64) as usize
+        )
+    }
+    fn to_share(self, size: types::Area) -> GeneratorPoint {
+        GeneratorPoint::new(self.x as f64 / size.width as f64, self.y as f64 / size.height as f64)
+    }
+    fn from_share(share: GeneratorPoint, size: types::Area) -> Self {
+        RollVector::new(
+            (share.x * size.width as f64).round() as usize,
+            (share.y * size.height as f64).round() as usize
+        )
+    }
 }
 
-pub fn generate(
-    size: types::Area, generator: &Generators, params: &GeneratorParams
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum AntiAliasing {
+    //Supersample the generators that don't anti-alias themselves.
+    #[default]
+    Auto,
+    //Never supersample. A quarter of the work, but sharp edges get jaggy.
+    Off,
+    //Supersample every generator, even the ones that are already smooth.
+    Always,
+}
+
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum AntiAliasKernel {
+    //Every supersample counts the same.
+    #[default]
+    Box,
+    //Samples count less the further they are from the pixel, falling off in a straight line.
+    Tent,
+    //Samples count less the further they are from the pixel, falling off along a bell curve.
+    Gaussian,
+}
+impl AntiAliasKernel {
+    fn weight(&self, distance: f64) -> f64 {
+        //The distance is measured in supersample steps from the pixel itself.
+        match self {
+            AntiAliasKernel::Box => 1.0,
+            AntiAliasKernel::Tent => f64::max(0.0, 1.0 - distance / 2.0),
+            AntiAliasKernel::Gaussian => (-distance * distance / 2.0).exp(),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum ClipMethod {
+    //Chop anything outside 0..1 off flat at the bound.
+    #[default]
+    Hard,
+    /*
+    Leave values more than the shoulder away from either bound alone, and
+    bend the rest smoothly towards the bound without ever quite reaching it.
+    A shoulder of 0.1 keeps 0.1..0.9 exactly as it was; the shoulder can be
+    up to 0.5.
+    */
+    Soft(f64),
+}
+impl ClipMethod {
+    fn apply(&self, value: f64) -> f64 {
+        match *self {
+            ClipMethod::Soft(shoulder) if shoulder > 0.0 => {
+                /*
+                Past the start of the shoulder the distance still to go to the
+                bound decays exponentially. It starts out at the same slope as
+                the value itself, so there is no crease where the shoulder
+                begins, and the bound itself comes out shoulder / e away.
+                */
+                let shoulder = shoulder.min(0.5);
+                if value < shoulder {
+                    shoulder * ((value - shoulder) / shoulder).exp()
+                } else if value > 1.0 - shoulder {
+                    1.0 - shoulder * ((1.0 - shoulder - value) / shoulder).exp()
+                } else {
+                    value
+                }
+            },
+            _ => value.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct GenerateOptions {
+    pub anti_aliasing: AntiAliasing,
+    pub kernel: AntiAliasKernel,
+    /*
+    Leave the texture where the generator put it instead of rolling it a
+    random way around the tile, so the same params always land on the same
+    pixels.
+    */
+    pub no_roll: bool,
+    //How values that land outside, or right at the edge of, 0..1 are brought into range.
+    pub clip: ClipMethod,
+    /*
+    How many times the pattern repeats across and down the image. Every copy
+    is a whole tile, so the image still wraps seamlessly at its edges.
+    A count of 0 is taken as 1, so the default is a single tile.
+    */
+    pub repeat: (u32, u32),
+}
+impl GenerateOptions {
+    fn repeat(&self) -> (usize, usize) {
+        (self.repeat.0.max(1) as usize, self.repeat.1.max(1) as usize)
+    }
+}
+
+pub fn generate<R: Rng + ?Sized>(
+    size: types::Area, generator: &Generators, params: &GeneratorParams, rng: &mut R
+) -> types::PixelMap {
+    generate_with_options(size, generator, params, &Default::default(), rng)
+}
+
+pub fn generate_with_options<R: Rng + ?Sized>(
+    size: types::Area, generator: &Generators, params: &GeneratorParams,
+    options: &GenerateOptions, rng: &mut R
 ) -> types::PixelMap {
     /*
     Create a texture of appropriate dimensions from this generator.
@@ -195,34 +573,374 @@ pub fn generate(
     The end result of Generate is either NULL, or a greybuf containing an anti-aliased,
     seamlessly wrapped greyscale 8-bit monolayer texture.
     We don't care what happens to the greybuf after we produce it.
+    The roll around the tile is the only random part of generating a
+    texture, and it is drawn from rng, so the same params and an rng in the
+    same state always give back exactly the same texture.
     */
-    let mut rng = rand::thread_rng();
+    let roll = pick_roll(size, generator, options, rng);
+    generate_with_roll(size, roll, generator, params, options)
+}
 
-    let roll = RollVector::new(
-        rng.gen_range(0..=size.width),
-        rng.gen_range(0..=size.height)
-    );
+fn pick_roll<R: Rng + ?Sized>(
+    size: types::Area, generator: &Generators, options: &GenerateOptions, rng: &mut R
+) -> RollVector {
+    /*
+    A gradient already says where it starts, so it is never rolled off its
+    origin, and a pixel grid puts its lines on exact rows and columns.
+    */
+    match generator {
+        _ if options.no_roll => RollVector::new(0, 0),
+        Generators::Gradient | Generators::PixelGrid => RollVector::new(0, 0),
+        _ => RollVector::random_with(size, rng),
+    }
+}
+
+pub(crate) fn random_roll<R: Rng + ?Sized>(
+    size: types::Area, generator: &Generators, rng: &mut R
+) -> GeneratorPoint {
+    /*
+    Pick a roll around the tile the way generate does, but hand it back as
+    a share of the size instead of using it, so the same texture can be
+    drawn again with generate_at at any size.
+    */
+    pick_roll(size, generator, &Default::default(), rng).to_share(size)
+}
 
-    vec![vec![0 as f64; size.width]; size.height].iter().enumerate().map(
-        |(y, line)|
-            line.iter().enumerate().map(
-                |(x, _)|
-                    f64::min(1.0, f64::max(0.0, get_layer_pixel(
-                        types::PixelPoint::new(x, y), size, roll, generator, params
-                    ).unwrap()))
-            ).collect()
+pub(crate) fn generate_at(
+    size: types::Area, roll: GeneratorPoint, generator: &Generators, params: &GeneratorParams
+) -> types::PixelMap {
+    //Same as generate, rolled by a share of the size from random_roll.
+    let roll = RollVector::from_share(roll, size);
+    generate_with_roll(size, roll, generator, params, &Default::default())
+}
+
+pub(crate) fn generate_mix_at(
+    size: types::Area,
+    roll: GeneratorPoint,
+    first: (&Generators, &GeneratorParams),
+    second: (&Generators, &GeneratorParams),
+    weight: f64
+) -> Result<types::PixelMap, String> {
+    //Same as generate_mix, rolled by a share of the size from random_roll.
+    mix_with_roll(size, RollVector::from_share(roll, size), first, second, weight)
+}
+
+#[cfg(feature = "entropy")]
+pub(crate) fn generate_row_at(
+    size: types::Area, roll: GeneratorPoint, generator: &Generators, params: &GeneratorParams,
+    y: usize
+) -> Vec<f64> {
+    //Row y of what generate_at draws, without drawing the others.
+    let roll = RollVector::from_share(roll, size);
+    row_with_roll(size, roll, generator, params, &Default::default(), y)
+}
+
+#[cfg(feature = "entropy")]
+pub(crate) fn generate_mix_row_at(
+    size: types::Area,
+    roll: GeneratorPoint,
+    first: (&Generators, &GeneratorParams),
+    second: (&Generators, &GeneratorParams),
+    weight: f64,
+    y: usize
+) -> Result<Vec<f64>, String> {
+    //Row y of what generate_mix_at draws, without drawing the others.
+    mix_row_with_roll(size, RollVector::from_share(roll, size), first, second, weight, y)
+}
+
+#[cfg(feature = "entropy")]
+pub fn generate_with(size: types::Area, generator: &dyn Generator) -> types::PixelMap {
+    /*
+    Create a texture from any generator, built in or not. It goes through
+    the same steps as generate: rolled a random way around the tile, its
+    edges blended unless it tiles on its own, and supersampled unless it
+    smooths its own edges.
+    */
+    generate_dyn_with_rng(size, generator, &mut game::get_rng())
+}
+
+pub fn generate_dyn_with_rng<R: Rng + ?Sized>(
+    size: types::Area, generator: &dyn Generator, rng: &mut R
+) -> types::PixelMap {
+    //Same as generate_with, but rolled by the given rng.
+    let roll = RollVector::random_with(size, rng);
+    let options = GenerateOptions::default();
+    (0..size.height).map(
+        |y| (0..size.width).map(
+            |x| {
+                let (point, fudge) =
+                    to_generator_point(types::PixelPoint::new(x, y), size, roll, &options);
+                get_anti_aliased_point(point, fudge, generator, &options).clamp(0.0, 1.0)
+            }
+        ).collect()
+    ).collect()
+}
+
+#[cfg(feature = "entropy")]
+pub fn generate_ranged(
+    size: types::Area, generator: &Generators, params: &GeneratorParams, min: f64, max: f64
+) -> types::PixelMap {
+    /*
+    Same as generate, but the texture is stretched from its usual 0..1
+    onto min..max, e.g. -1..1 for a signed heightmap or 0..255 for bytes.
+    0 maps to min and 1 to max; a max below min simply turns it upside down.
+    */
+    stretch(generate(size, generator, params, &mut game::get_rng()), min, max)
+}
+
+pub fn generate_ranged_with_rng<R: Rng + ?Sized>(
+    size: types::Area, generator: &Generators, params: &GeneratorParams, min: f64, max: f64,
+    rng: &mut R
+) -> types::PixelMap {
+    //Same as generate_ranged, but rolled by the given rng.
+    stretch(generate(size, generator, params, rng), min, max)
+}
+fn stretch(map: types::PixelMap, min: f64, max: f64) -> types::PixelMap {
+    //Anything a generator let slip out of 0..1 is clamped first, so it stays inside min..max.
+    map.into_iter().map(
+        |line| line.into_iter().map(|value| min + value.clamp(0.0, 1.0) * (max - min)).collect()
     ).collect()
 }
 
+#[cfg(feature = "entropy")]
+pub fn generate_mix(
+    size: types::Area,
+    first: (&Generators, &GeneratorParams),
+    second: (&Generators, &GeneratorParams),
+    weight: f64
+) -> Result<types::PixelMap, String> {
+    /*
+    Create a texture that is a weighted blend of two generators.
+    The weight is the share of the first generator; the second one makes
+    up the rest. Both textures are rolled by the same amount, so a weight
+    of 1.0 or 0.0 gives back exactly what that generator would make alone.
+    */
+    generate_mix_with_rng(size, first, second, weight, &mut game::get_rng())
+}
+
+pub fn generate_mix_with_rng<R: Rng + ?Sized>(
+    size: types::Area,
+    first: (&Generators, &GeneratorParams),
+    second: (&Generators, &GeneratorParams),
+    weight: f64,
+    rng: &mut R
+) -> Result<types::PixelMap, String> {
+    //Same as generate_mix, but rolled by the given rng.
+    mix_with_roll(size, RollVector::random_with(size, rng), first, second, weight)
+}
+
+fn mix_with_roll(
+    size: types::Area,
+    roll: RollVector,
+    first: (&Generators, &GeneratorParams),
+    second: (&Generators, &GeneratorParams),
+    weight: f64
+) -> Result<types::PixelMap, String> {
+    (0..size.height).map(|y| mix_row_with_roll(size, roll, first, second, weight, y)).collect()
+}
+fn mix_row_with_roll(
+    size: types::Area,
+    roll: RollVector,
+    first: (&Generators, &GeneratorParams),
+    second: (&Generators, &GeneratorParams),
+    weight: f64,
+    y: usize
+) -> Result<Vec<f64>, String> {
+    if !(0.0..=1.0).contains(&weight) {
+        return Err("must be 0.0 <= weight <= 1.0".to_string());
+    }
+    let options = Default::default();
+    let first = row_with_roll(size, roll, first.0, first.1, &options, y);
+    let second = row_with_roll(size, roll, second.0, second.1, &options, y);
+    Ok(first.iter().zip(second.iter()).map(
+        |(first, second)| first * weight + second * (1.0 - weight)
+    ).collect())
+}
+
+#[cfg(feature = "entropy")]
+pub fn generate_atlas(
+    size: types::Area, count: usize, generator: &Generators, base_params: &GeneratorParams
+) -> Vec<types::PixelMap> {
+    /*
+    Create a set of textures that can be laid next to each other in any
+    order without visible seams.
+    Every tile shares one base texture, which tiles seamlessly with itself.
+    Each tile mixes a variation into it - the same texture rolled to a
+    fresh spot - weighted the way the edge wrap weights its out-of-band
+    values: fully in the middle of the tile, fading to nothing at its
+    borders. Every tile's borders are therefore exactly the base texture's
+    borders, so tile i's right edge runs into tile i+1's left edge just as
+    the base texture's right edge runs into its own left edge.
+    Every variation is rolled from the same rng, so one seed gives one atlas.
+    */
+    generate_atlas_with_rng(size, count, generator, base_params, &mut game::get_rng())
+}
+
+pub fn generate_atlas_with_rng<R: Rng + ?Sized>(
+    size: types::Area, count: usize, generator: &Generators, base_params: &GeneratorParams,
+    rng: &mut R
+) -> Vec<types::PixelMap> {
+    //Same as generate_atlas, but every variation is rolled by the given rng.
+    let options = Default::default();
+    let base = generate_with_roll(size, RollVector::new(0, 0), generator, base_params, &options);
+    (0..count).map(|_| {
+        let variation = generate_with_roll(
+            size, RollVector::random_with(size, rng), generator, base_params, &options
+        );
+        base.iter().zip(variation.iter()).enumerate().map(
+            |(y, (base, variation))|
+                base.iter().zip(variation.iter()).enumerate().map(
+                    |(x, (base, variation))| {
+                        let weight = edge_window(x, size.width) * edge_window(y, size.height);
+                        variation * weight + base * (1.0 - weight)
+                    }
+                ).collect()
+        ).collect()
+    }).collect()
+}
+
+fn edge_window(index: usize, length: usize) -> f64 {
+    //Zero on the first and last pixels, rising to one in the middle.
+    if length < 2 {
+        return 0.0;
+    }
+    let position = index as f64 / (length - 1) as f64;
+    4.0 * position * (1.0 - position)
+}
+
+#[cfg(feature = "entropy")]
+pub fn generate_1d(
+    length: usize, generator: &Generators, params: &GeneratorParams
+) -> Vec<f64> {
+    /*
+    Create a strip of values along a single line through the texture.
+    This is just a texture one pixel high, so it wraps seamlessly from its
+    last value back to its first, the same way a full texture does.
+    */
+    generate(types::Area::new(length, 1), generator, params, &mut game::get_rng()).remove(0)
+}
+
+pub fn generate_1d_with_rng<R: Rng + ?Sized>(
+    length: usize, generator: &Generators, params: &GeneratorParams, rng: &mut R
+) -> Vec<f64> {
+    //Same as generate_1d, but rolled by the given rng.
+    generate(types::Area::new(length, 1), generator, params, rng).remove(0)
+}
+
+fn generate_with_roll(
+    size: types::Area, roll: RollVector, generator: &Generators, params: &GeneratorParams,
+    options: &GenerateOptions
+) -> types::PixelMap {
+    (0..size.height).map(|y| row_with_roll(size, roll, generator, params, options, y)).collect()
+}
+fn row_with_roll(
+    size: types::Area, roll: RollVector, generator: &Generators, params: &GeneratorParams,
+    options: &GenerateOptions, y: usize
+) -> Vec<f64> {
+    //Every pixel is worked out on its own, so one row can be drawn without the rest.
+    (0..size.width).map(
+        |x|
+            get_layer_pixel(
+                types::PixelPoint::new(x, y), size, roll, generator, params, options
+            ).unwrap().clamp(0.0, 1.0)
+    ).collect()
+}
+
+#[cfg(feature = "debug-stages")]
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Clone)]
+pub struct DebugStages {
+    //What the generator itself returned, before the edges were wrapped.
+    pub unwrapped: types::PixelMap,
+    //After the edges were wrapped, before out-of-range values were clipped.
+    pub unclamped: types::PixelMap,
+    //After clipping, before anti-aliasing.
+    pub unaliased: types::PixelMap,
+    //The finished texture, the same as generate would give.
+    pub output: types::PixelMap,
+}
+
+#[cfg(feature = "debug-stages")]
+pub fn generate_debug<R: Rng + ?Sized>(
+    size: types::Area, generator: &Generators, params: &GeneratorParams,
+    options: &GenerateOptions, rng: &mut R
+) -> DebugStages {
+    /*
+    Same as generate_with_options, but also hand back the texture as it
+    looked between each step of the pixel pipeline, so a developer can see
+    which step an artifact comes from. Only the first sample of each pixel
+    is kept for the early stages; anti-aliasing adds the rest.
+    The texture is rolled just like generate does, so from an rng in the
+    same state the output matches generate.
+    */
+    let roll = pick_roll(size, generator, options, rng);
+    let mut stages = DebugStages::default();
+    for y in 0..size.height {
+        let mut rows: [Vec<f64>; 4] = Default::default();
+        for x in 0..size.width {
+            let pixel = types::PixelPoint::new(x, y);
+            let (tile_pixel, tile) = repeat_pixel(roll_pixel(pixel, size, roll), size, options);
+            let absolute = call_absolute_generator(tile_pixel, tile, generator, params);
+            if let Some(value) = absolute {
+                //Generators that draw whole pixels skip every step in between.
+                for row in rows.iter_mut() {
+                    row.push(value);
+                }
+                continue;
+            }
+            let (point, fudge) = to_generator_point(pixel, size, roll, options);
+            let generator = generator.with_params(params);
+            let unclamped = get_unclamped_point(point, generator);
+            rows[0].push(generator.sample_point(point.x, point.y));
+            rows[1].push(unclamped);
+            rows[2].push(options.clip.apply(unclamped));
+            rows[3].push(
+                get_anti_aliased_point(point, fudge, generator, options)
+                    .clamp(0.0, 1.0)
+            );
+        }
+        let [unwrapped, unclamped, unaliased, output] = rows;
+        stages.unwrapped.push(unwrapped);
+        stages.unclamped.push(unclamped);
+        stages.unaliased.push(unaliased);
+        stages.output.push(output);
+    }
+    stages
+}
+
 #[derive(Debug)]
 #[derive(Default)]
 #[derive(Clone)]
 #[derive(Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "GeneratorPointData"))]
 pub struct GeneratorPoint {
     // must be 0.0..=1.0
     x: f64,
     y: f64,
 }
+
+//A point as it is read back in, before it is checked to be on the tile.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct GeneratorPointData {
+    x: f64,
+    y: f64,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<GeneratorPointData> for GeneratorPoint {
+    type Error = String;
+
+    fn try_from(data: GeneratorPointData) -> Result<Self, Self::Error> {
+        //A range check also turns away NaN, which is in no range.
+        if !(0.0..=1.0).contains(&data.x) || !(0.0..=1.0).contains(&data.y) {
+            return Err(format!("must be 0.0 <= x/y <= 1.0, not {}, {}", data.x, data.y));
+        }
+        Ok(GeneratorPoint::new(data.x, data.y))
+    }
+}
 impl GeneratorPoint {
     pub fn new(x: f64, y: f64) -> Self {
         GeneratorPoint {
@@ -230,6 +948,12 @@ impl GeneratorPoint {
             y,
         }
     }
+    pub fn x(&self) -> f64 {
+        self.x
+    }
+    pub fn y(&self) -> f64 {
+        self.y
+    }
 }
 impl Distribution<GeneratorPoint> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> GeneratorPoint {
@@ -244,10 +968,12 @@ fn get_layer_pixel(
     pixel: types::PixelPoint,
     size: types:: Area,
     roll: RollVector,
-    generator: &Generators, params: &GeneratorParams
+    generator: &Generators, params: &GeneratorParams,
+    options: &GenerateOptions
 ) -> Result<f64, String> {
-    if pixel.x >= size.width && pixel.y >= size.height {
-        return Err(format!("pixel.x >= {} && pixel.y >= {}", size.width, size.height));
+    //Nothing here is random; the roll was already drawn from the one rng the texture is made with.
+    if pixel.x >= size.width || pixel.y >= size.height {
+        return Err(format!("pixel.x >= {} || pixel.y >= {}", size.width, size.height));
     }
     /*
     Calculate the point they wanted.
@@ -258,25 +984,91 @@ fn get_layer_pixel(
     code. Then we convert the floating point value to a standard 0..255
     value and return it to the caller.
     */
-    let pixel = GeneratorPoint::new(
-        (
-            if pixel.x + roll.x < size.width {pixel.x + roll.x}
-            else {pixel.x + roll.x - size.width}
-        ) as f64 / size.width as f64,
-        (
-            if pixel.y + roll.y < size.height {pixel.y + roll.y}
-            else {pixel.y + roll.y - size.height}
-        ) as f64 / size.height as f64
+    let (tile_pixel, tile) = repeat_pixel(roll_pixel(pixel, size, roll), size, options);
+    let absolute = call_absolute_generator(tile_pixel, tile, generator, params);
+    if let Some(value) = absolute {
+        return Ok(value);
+    }
+    let (pixel, fudge) = to_generator_point(pixel, size, roll, options);
+    Ok(get_anti_aliased_point(pixel, fudge, generator.with_params(params), options))
+}
+
+fn roll_pixel(pixel: types::PixelPoint, size: types::Area, roll: RollVector) -> types::PixelPoint {
+    /*
+    Move the pixel along by the roll, wrapping round the tile. Both are
+    brought onto the tile first, and whatever would pass the far edge starts
+    again from the near one, so the sum is never worked out in full: not
+    even a pixel and a roll near usize::MAX can overflow.
+    */
+    let wrap = |at: usize, by: usize, length: usize| {
+        let (at, by) = (at % length, by % length);
+        if at >= length - by {at - (length - by)} else {at + by}
+    };
+    types::PixelPoint::new(wrap(pixel.x, roll.x, size.width), wrap(pixel.y, roll.y, size.height))
+}
+
+fn repeat_pixel(
+    pixel: types::PixelPoint, size: types::Area, options: &GenerateOptions
+) -> (types::PixelPoint, types::Area) {
+    /*
+    Cut the image into as many whole-pixel tiles as it repeats, and hand back
+    which pixel of which tile this is. When the size does not divide evenly
+    the last tile in a row or column is left short.
+    */
+    let (across, down) = options.repeat();
+    let tile = types::Area::new(
+        usize::max(1, size.width / across), usize::max(1, size.height / down)
     );
-    let fudge = 1.0 / (size.width + size.height) as f64;
-    Ok(get_anti_aliased_point(pixel, fudge, generator, params))
+    (types::PixelPoint::new(pixel.x % tile.width, pixel.y % tile.height), tile)
+}
+
+fn call_absolute_generator(
+    pixel: types::PixelPoint, size: types::Area, generator: &Generators, params: &GeneratorParams
+) -> Option<f64> {
+    /*
+    A few generators want to know exactly which pixel they are drawing, for
+    features that have to land on whole pixels. They get the pixel and the
+    image size as they are, and what they return is the final value: it is
+    not wrapped or anti-aliased, since either would smear the pixels they
+    meant to hit. Every other generator returns None here and goes through
+    generator space as usual.
+    */
+    match generator {
+        Generators::PixelGrid => Some(pixelgrid::generate(pixel, size, &params.pixelgrid)),
+        _ => None,
+    }
+}
+
+fn to_generator_point(
+    pixel: types::PixelPoint, size: types::Area, roll: RollVector, options: &GenerateOptions
+) -> (GeneratorPoint, f64) {
+    /*
+    Roll the pixel around the tile and scale it into generator space.
+    The roll may be as large as the tile itself, so wrap with a modulo
+    rather than a single subtraction to always land back inside the tile.
+    To repeat the pattern, the point is placed within its tile, the same
+    whole-pixel tiles the absolute generators see, and the fudge grows to
+    match the smaller tiles.
+    */
+    let (pixel, tile) = repeat_pixel(roll_pixel(pixel, size, roll), size, options);
+    let point = GeneratorPoint::new(
+        pixel.x as f64 / tile.width as f64, pixel.y as f64 / tile.height as f64
+    );
+    let fudge = 1.0 / (tile.width + tile.height) as f64;
+    (point, fudge)
 }
 
 fn get_anti_aliased_point(
-    pixel: GeneratorPoint, fudge: f64, generator: &Generators, params: &GeneratorParams
+    pixel: GeneratorPoint, fudge: f64, generator: &dyn Generator, options: &GenerateOptions
 ) -> f64 {
-    let mut value = get_wrapped_point(pixel, generator, params);
-    if !GeneratorProperty::get(generator).is_anti_aliased {
+    let clip = &options.clip;
+    let value = get_wrapped_point(pixel, generator, clip);
+    let supersample = match options.anti_aliasing {
+        AntiAliasing::Auto => !generator.is_anti_aliased(),
+        AntiAliasing::Off => false,
+        AntiAliasing::Always => true,
+    };
+    if supersample {
         /*
         This generator does not anti-alias itself.
         We need to do the anti-aliasing for it.
@@ -287,23 +1079,57 @@ fn get_anti_aliased_point(
         improves the way sharp transitions look. You can't see the individual
         pixels nearly so easily.
         */
-        value += get_wrapped_point(
-            GeneratorPoint::new(pixel.x + fudge, pixel.y), generator, params
-        );
-        value += get_wrapped_point(
-            GeneratorPoint::new(pixel.x, pixel.y + fudge), generator, params
-        );
-        value += get_wrapped_point(
-            GeneratorPoint::new(pixel.x + fudge, pixel.y + fudge), generator, params
-        );
-        value /= 4.0;
+        let kernel = &options.kernel;
+        if let AntiAliasKernel::Box = kernel {
+            //The box keeps the points it always had: a fudge to the right, below, and both.
+            let [right, below, both] = [(fudge, 0.0), (0.0, fudge), (fudge, fudge)].map(
+                |(dx, dy)| get_wrapped_point(
+                    GeneratorPoint::new(pixel.x + dx, pixel.y + dy), generator, clip
+                )
+            );
+            return (value + right + below + both) / 4.0;
+        }
+        /*
+        The weighted kernels decide how much each point counts, and the
+        weights are divided out again at the end, so a flat area stays just
+        as bright. Their extra points sit half a fudge away along both axes,
+        one towards each corner, so the pixel stays at the middle of its
+        samples and a heavier middle sharpens the edges evenly on every side.
+        A step is half a fudge, so the corners are the square root of two
+        steps away. Points off the edge of the tile are taken from its other
+        side, where the tiled texture would find them.
+        */
+        let half = fudge / 2.0;
+        let corners: f64 = [(-half, -half), (half, -half), (-half, half), (half, half)].iter().map(
+            |(dx, dy)| {
+                let point = GeneratorPoint::new(
+                    (pixel.x + dx).rem_euclid(1.0), (pixel.y + dy).rem_euclid(1.0)
+                );
+                get_wrapped_point(point, generator, clip)
+            }
+        ).sum();
+        let corner = kernel.weight(std::f64::consts::SQRT_2);
+        let total = value * kernel.weight(0.0) + corners * corner;
+        return total / (kernel.weight(0.0) + corner * 4.0);
     }
     value
 }
 
-fn get_wrapped_point(
-    pixel: GeneratorPoint, generator: &Generators, params: &GeneratorParams
-) -> f64 {
+fn get_wrapped_point(pixel: GeneratorPoint, generator: &dyn Generator, clip: &ClipMethod) -> f64 {
+    /*
+    If the generator messes up and returns an out-of-range value, we clip it here.
+    This way, curves that leap out of bounds simply get chopped off, instead of getting
+    renormalized at the opposite end of the scale leading to big discontinuities and ugliness.
+    This can mask bugs in a generator, but we aren't the generator so we don't care.
+    If you're writing a generator it is your job to make your code work, and my job to
+    make sure my code works even if yours doesn't.
+    The clip can also be soft, which rounds the values off as they near the
+    bounds instead of chopping them flat.
+    */
+    clip.apply(get_unclamped_point(pixel, generator))
+}
+
+fn get_unclamped_point(pixel: GeneratorPoint, generator: &dyn Generator) -> f64 {
     /*
     Get a point from this function.
     But don't just get the point - also get some out-of-band values and mix
@@ -312,13 +1138,13 @@ fn get_wrapped_point(
     Some functions do this on their own; if that's the case, we let it do it.
     Otherwise, we do the computations ourself.
     */
-    let mut value = call_generator(pixel, generator, params);
+    let mut value = generator.sample_point(pixel.x, pixel.y);
     /*
     If this function does not generate seamlessly-tiled textures,
     then it is our job to pull in out-of-band data and mix it in
     with the actual pixel to get a smooth edge.
     */
-    if !GeneratorProperty::get(generator).is_seamless {
+    if generator.wraps_edges() {
         /*
         We mix this pixel with out-of-band values from the opposite side
         of the tile. This is a "weighted average" proportionate to the pixel's
@@ -330,9 +1156,9 @@ fn get_wrapped_point(
         let farh = pixel.x + 1.0;
         let farv = pixel.y + 1.0;
         //There are three pixel values to grab off the edges.
-        let farval1 = call_generator(GeneratorPoint::new(pixel.x, farv), generator, params);
-        let farval2 = call_generator(GeneratorPoint::new(farh, pixel.y), generator, params);
-        let farval3 = call_generator(GeneratorPoint::new(farh, farv), generator, params);
+        let farval1 = generator.sample_point(pixel.x, farv);
+        let farval2 = generator.sample_point(farh, pixel.y);
+        let farval3 = generator.sample_point(farh, farv);
         //Calculate the weight factors for each far point.
         let weight = pixel.x * pixel.y;
         let farweight1 = pixel.x * (2.0 - farv);
@@ -347,31 +1173,419 @@ fn get_wrapped_point(
             + (farval3 * farweight3)
         ) / totalweight;
     }
-    /*
-    If the generator messes up and returns an out-of-range value, we clip it here.
-    This way, curves that leap out of bounds simply get chopped off, instead of getting
-    renormalized at the opposite end of the scale leading to big discontinuities and ugliness.
-    This can mask bugs in a generator, but we aren't the generator so we don't care.
-    If you're writing a generator it is your job to make your code work, and my job to
-    make sure my code works even if yours doesn't.
-    */
-    if value > 1.0 {1.0} else if value < 0.0 {0.0} else {value}
+    value
 }
 
-fn call_generator(
-    pixel: GeneratorPoint, generator: &Generators, params: &GeneratorParams
-) -> f64 {
-    match generator {
-        Generators::Coswave
-            => coswave::generate(pixel, &params.coswave),
-        Generators::Spinflake
-            => spinflake::generate(pixel, &params.spinflake),
-        Generators::Rangefrac
-            => rangefrac::generate(pixel, &params.rangefrac),
-        Generators::Flatwave
-            => flatwave::generate(pixel, &params.flatwave),
-        Generators::Bubble
-            => bubble::generate(pixel, &params.bubble),
-        _ => test::generate(pixel.x, pixel.y),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::game;
+    use super::super::rng::SeedableRng;
+
+    fn every_generator() -> Vec<Generators> {
+        //Every generator with params of its own, not just the ones picked for layers.
+        Generators::every().into_iter().filter(|generator| *generator != Generators::Test).collect()
+    }
+
+    struct Step;
+    impl Generator for Step {
+        fn sample_point(&self, x: f64, _: f64) -> f64 {
+            if x.rem_euclid(1.0) < 0.5 {0.0} else {1.0}
+        }
+        fn is_seamless(&self) -> bool {
+            true
+        }
+        fn is_anti_aliased(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn kernels_keep_the_mean_but_change_edges() {
+        //One row of a 64x64 texture, with the step halfway along it.
+        let row = |kernel| {
+            let options = GenerateOptions {
+                kernel,
+                ..Default::default()
+            };
+            (0..64).map(
+                |x| get_anti_aliased_point(
+                    GeneratorPoint::new(x as f64 / 64.0, 0.5), 1.0 / 128.0, &Step, &options
+                )
+            ).collect::<Vec<_>>()
+        };
+        let boxed = row(AntiAliasKernel::Box);
+        for kernel in &[AntiAliasKernel::Tent, AntiAliasKernel::Gaussian] {
+            let weighted = row(*kernel);
+            let mean = |row: &[f64]| row.iter().sum::<f64>() / row.len() as f64;
+            assert!((mean(&weighted) - mean(&boxed)).abs() < 1e-12);
+            /*
+            The box only looks right of each pixel, so the pixel on the step
+            already has all of it. The weighted kernels look both ways and
+            see only part of it, though more than half, since the pixel's own
+            sample counts for more.
+            */
+            let edge = weighted[32];
+            assert!(0.5 < edge && edge < boxed[32], "{:?}: {}", kernel, edge);
+            //Away from the step every sample agrees, whatever they weigh.
+            assert_eq!((weighted[16], weighted[48]), (boxed[16], boxed[48]));
+        }
+    }
+
+    #[test]
+    fn the_box_kernel_samples_where_it_always_did() {
+        //The four points that were averaged before there were kernels to pick from.
+        let old_box = |pixel: GeneratorPoint, fudge: f64, generator: &dyn Generator| {
+            let at = |x, y| get_wrapped_point(
+                GeneratorPoint::new(x, y), generator, &ClipMethod::Hard
+            );
+            let mut value = at(pixel.x, pixel.y);
+            value += at(pixel.x + fudge, pixel.y);
+            value += at(pixel.x, pixel.y + fudge);
+            value += at(pixel.x + fudge, pixel.y + fudge);
+            value / 4.0
+        };
+        let params: GeneratorParams = game::GameRng::seed_from_u64(3).gen();
+        let options = GenerateOptions {anti_aliasing: AntiAliasing::Always, ..Default::default()};
+        for generator in every_generator() {
+            let generator = generator.with_params(&params);
+            for i in 0..32 * 32 {
+                let pixel = GeneratorPoint::new((i % 32) as f64 / 32.0, (i / 32) as f64 / 32.0);
+                assert_eq!(
+                    get_anti_aliased_point(pixel, 1.0 / 64.0, generator, &options),
+                    old_box(pixel, 1.0 / 64.0, generator)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_generator_is_wired_to_its_own_params() {
+        //A generator left pointing at the placeholder would draw the test pattern instead.
+        let params: GeneratorParams = game::GameRng::seed_from_u64(1).gen();
+        let points = (0..8).flat_map(
+            |x| (0..8).map(move |y| GeneratorPoint::new(x as f64 / 8.0, y as f64 / 8.0))
+        );
+        for generator in every_generator() {
+            assert!(
+                points.clone().any(
+                    |point| generator.with_params(&params).sample_point(point.x, point.y)
+                        != test::generate(point.x, point.y)
+                ),
+                "{} draws the test pattern", generator.name()
+            );
+        }
+    }
+
+    #[test]
+    fn repeats_place_points_within_the_tile() {
+        let size = types::Area::new(10, 8);
+        let point = |x, y, repeat| to_generator_point(
+            types::PixelPoint::new(x, y), size, RollVector::new(0, 0),
+            &GenerateOptions {
+                repeat,
+                ..Default::default()
+            }
+        );
+        //A single tile is the whole image.
+        let (single, fudge) = point(7, 3, (1, 1));
+        assert_eq!((single.x, single.y, fudge), (0.7, 3.0 / 8.0, 1.0 / 18.0));
+        //Three across leaves whole tiles of three pixels, and a short one at the end.
+        let (repeated, fudge) = point(7, 3, (3, 2));
+        assert_eq!((repeated.x, repeated.y, fudge), (1.0 / 3.0, 3.0 / 4.0, 1.0 / 7.0));
+        let (short, _) = point(9, 0, (3, 2));
+        assert_eq!(short.x, 0.0);
+    }
+
+    #[test]
+    fn layer_pixels_past_either_edge_are_an_error() {
+        let (size, params) = (types::Area::new(16, 8), GeneratorParams::default());
+        let pixel = |x, y| get_layer_pixel(
+            types::PixelPoint::new(x, y), size, RollVector::new(0, 0), &Generators::Coswave,
+            &params, &Default::default()
+        );
+        assert!(pixel(15, 7).is_ok());
+        assert!(pixel(16, 0).is_err());
+        assert!(pixel(0, 8).is_err());
+    }
+
+    #[test]
+    fn the_same_rng_draws_the_same_texture() {
+        let size = types::Area::new(24, 16);
+        let params: GeneratorParams = game::GameRng::seed_from_u64(1).gen();
+        for generator in Generators::all() {
+            let draw = || generate(size, &generator, &params, &mut game::GameRng::seed_from_u64(2));
+            assert_eq!(draw(), draw(), "{}", generator.name());
+        }
+    }
+
+    #[test]
+    fn the_origin_column_is_finite() {
+        //Straight above and below the origin, and on it, the angle has no x to divide by.
+        let origin = GeneratorPoint::new(0.5, 0.5);
+        for seed in 0..4 {
+            let mut params: GeneratorParams = game::GameRng::seed_from_u64(seed).gen();
+            params.set_origin(origin);
+            for generator in every_generator() {
+                for y in 0..=64 {
+                    let value = generator.with_params(&params).sample_point(0.5, y as f64 / 64.0);
+                    assert!(value.is_finite(), "{} gives {} at y {}", generator.name(), value, y);
+                }
+            }
+        }
+        assert_eq!(angle_and_dist(0.0, 0.0), (0.0, 0.0));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn params_read_back_from_json_draw_the_same_textures() {
+        let size = types::Area::new(24, 16);
+        let params: GeneratorParams = game::GameRng::seed_from_u64(1).gen();
+        let json = serde_json::to_string(&params).unwrap();
+        let read: GeneratorParams = serde_json::from_str(&json).unwrap();
+        for generator in every_generator() {
+            let roll = RollVector::new(3, 5);
+            let options = Default::default();
+            let draw = |params| generate_with_roll(size, roll, &generator, params, &options);
+            assert_eq!(draw(&read), draw(&params), "{}", generator.name());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn points_off_the_tile_are_not_read() {
+        assert!(serde_json::from_str::<GeneratorPoint>(r#"{"x":0.0,"y":1.0}"#).is_ok());
+        for json in &[r#"{"x":1.5,"y":0.5}"#, r#"{"x":0.5,"y":-0.1}"#, r#"{"x":0.5,"y":1e400}"#] {
+            assert!(serde_json::from_str::<GeneratorPoint>(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn no_roll_never_moves_the_texture() {
+        let size = types::Area::new(24, 16);
+        let params: GeneratorParams = game::GameRng::seed_from_u64(1).gen();
+        let options = GenerateOptions {
+            no_roll: true,
+            ..Default::default()
+        };
+        for generator in Generators::all() {
+            let draw = |seed| generate_with_options(
+                size, &generator, &params, &options, &mut game::GameRng::seed_from_u64(seed)
+            );
+            //Rngs that would roll the texture to different places leave it where it is.
+            let unrolled =
+                generate_with_roll(size, RollVector::new(0, 0), &generator, &params, &options);
+            assert_eq!(draw(2), unrolled, "{}", generator.name());
+            assert_eq!(draw(3), unrolled, "{}", generator.name());
+        }
+    }
+
+    #[test]
+    fn default_params_are_not_flat() {
+        let size = types::Area::new(32, 32);
+        let params = GeneratorParams::default();
+        let options = GenerateOptions {
+            no_roll: true,
+            ..Default::default()
+        };
+        for generator in every_generator() {
+            let map = generate_with_options(
+                size, &generator, &params, &options, &mut game::GameRng::seed_from_u64(0)
+            );
+            let values = map.iter().flatten();
+            let min = values.clone().fold(f64::INFINITY, |min, &value| value.min(min));
+            let max = values.fold(f64::NEG_INFINITY, |max, &value| value.max(max));
+            assert!(max - min > 0.1, "{} only covers {}..{}", generator.name(), min, max);
+        }
+    }
+
+    #[test]
+    fn square_waves_only_give_two_values() {
+        for &duty in &[0.0, 0.25, 0.5, 0.75, 1.0] {
+            for step in 0..256 {
+                let value = packed_cos(step as f64 / 16.0, 1.0, &PackMethods::SquareToFit(duty));
+                assert!(value == 0.0 || value == 1.0, "{} at duty {}", value, duty);
+            }
+        }
+    }
+
+    #[test]
+    fn every_generator_is_found_by_its_name() {
+        for generator in Generators::every() {
+            assert_eq!(Generators::from_name(generator.name()), Some(generator));
+            assert_eq!(Generators::from_name(&generator.name().to_uppercase()), Some(generator));
+        }
+        assert_eq!(Generators::from_name("starfish"), None);
+    }
+
+    #[test]
+    fn every_generator_describes_itself() {
+        let params = GeneratorParams::default();
+        let infos: Vec<_> = Generators::every().iter().map(Generators::info).collect();
+        for (generator, info) in Generators::every().iter().zip(&infos) {
+            assert_eq!(info.name, generator.name());
+            assert!(!info.description.is_empty(), "{}", info.name);
+            let drawn = generator.with_params(&params);
+            assert_eq!(info.is_anti_aliased, drawn.is_anti_aliased(), "{}", info.name);
+        }
+        //No two generators share a description, so none was copied and left unchanged.
+        for (i, info) in infos.iter().enumerate() {
+            assert!(infos[..i].iter().all(|other| other.description != info.description));
+        }
+    }
+
+    #[test]
+    fn a_whole_weight_mixes_in_only_one_generator() {
+        let size = types::Area::new(24, 16);
+        let roll = RollVector::new(5, 3);
+        let params: GeneratorParams = game::GameRng::seed_from_u64(1).gen();
+        let (first, second) = (&Generators::Coswave, &Generators::Rangefrac);
+        let mix = |weight| mix_with_roll(size, roll, (first, &params), (second, &params), weight);
+        let options = Default::default();
+        let alone = |generator| generate_with_roll(size, roll, generator, &params, &options);
+        assert_eq!(mix(1.0).unwrap(), alone(first));
+        assert_eq!(mix(0.0).unwrap(), alone(second));
+        assert!(mix(1.5).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "debug-stages")]
+    fn the_last_debug_stage_is_the_texture() {
+        let size = types::Area::new(24, 16);
+        let params: GeneratorParams = game::GameRng::seed_from_u64(1).gen();
+        let options = Default::default();
+        for generator in every_generator() {
+            let rng = || game::GameRng::seed_from_u64(3);
+            let stages = generate_debug(size, &generator, &params, &options, &mut rng());
+            let plain = generate_with_options(size, &generator, &params, &options, &mut rng());
+            assert_eq!(stages.output, plain, "{}", generator.name());
+        }
+    }
+
+    #[test]
+    fn huge_rolls_of_huge_pixels_wrap_without_overflowing() {
+        let near_max = usize::MAX - 1;
+        let roll = RollVector::new(near_max, near_max);
+        let rolled = roll_pixel(
+            types::PixelPoint::new(near_max, 7), types::Area::new(usize::MAX, 10), roll
+        );
+        //usize::MAX is 5 more than a multiple of 10, so the roll steps 4 along the column.
+        assert_eq!((rolled.x, rolled.y), (usize::MAX - 2, 1));
+        let rolled = roll_pixel(types::PixelPoint::new(3, 0), types::Area::new(10, 1), roll);
+        assert_eq!((rolled.x, rolled.y), (7, 0));
+    }
+
+    #[test]
+    fn atlas_tiles_meet_each_other_as_the_base_meets_itself() {
+        let size = types::Area::new(24, 16);
+        let params: GeneratorParams = game::GameRng::seed_from_u64(1).gen();
+        for generator in Generators::all() {
+            let mut rng = game::GameRng::seed_from_u64(2);
+            let atlas = generate_atlas_with_rng(size, 3, &generator, &params, &mut rng);
+            let (unrolled, options) = (RollVector::new(0, 0), Default::default());
+            let base = generate_with_roll(size, unrolled, &generator, &params, &options);
+            let last = size.width - 1;
+            for (i, pair) in atlas.windows(2).enumerate() {
+                let (left, right) = (&pair[0], &pair[1]);
+                for y in 0..size.height {
+                    //Every seam between two tiles is the seam where the base wraps.
+                    assert_eq!(left[y][last], base[y][last], "{} tile {}", generator.name(), i);
+                    assert_eq!(right[y][0], base[y][0], "{} tile {}", generator.name(), i + 1);
+                }
+                assert_ne!(left, right, "{} tile {}", generator.name(), i);
+            }
+        }
+    }
+
+    #[test]
+    fn signed_ranges_put_the_middle_on_zero() {
+        let stretched = stretch(vec![vec![0.0, 0.25, 0.5, 1.0, 1.5, -0.5]], -1.0, 1.0);
+        assert_eq!(stretched, vec![vec![-1.0, -0.5, 0.0, 1.0, 1.0, -1.0]]);
+        //The same seed rolls the same way, so the stretched texture is the plain one scaled.
+        let size = types::Area::new(16, 8);
+        let params: GeneratorParams = game::GameRng::seed_from_u64(5).gen();
+        let draw = |min, max| generate_ranged_with_rng(
+            size, &Generators::Coswave, &params, min, max, &mut game::GameRng::seed_from_u64(5)
+        );
+        let (plain, bytes) = (draw(0.0, 1.0), draw(0.0, 255.0));
+        for (plain, byte) in plain.iter().flatten().zip(bytes.iter().flatten()) {
+            assert!((plain * 255.0 - byte).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn points_on_either_axis_get_exact_angles() {
+        //Dividing y by x would blow up for the points on the y axis.
+        use std::f64::consts::{FRAC_PI_2, PI};
+        let known = [
+            ((2.0, 0.0), (0.0, 2.0)),
+            ((-2.0, 0.0), (PI, 2.0)),
+            ((0.0, 3.0), (FRAC_PI_2, 3.0)),
+            ((0.0, -3.0), (-FRAC_PI_2, 3.0)),
+            ((-0.0, 3.0), (FRAC_PI_2, 3.0)),
+            ((3.0, 4.0), (4.0_f64.atan2(3.0), 5.0)),
+        ];
+        for ((x, y), expected) in known {
+            assert_eq!(angle_and_dist(x, y), expected, "at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn soft_clips_keep_the_middle_and_bend_the_ends() {
+        let soft = ClipMethod::Soft(0.1);
+        for i in 0..=80 {
+            let value = 0.1 + i as f64 / 100.0;
+            assert_eq!(soft.apply(value), value);
+        }
+        //Near and past the bounds values are squeezed in, but never reach them.
+        let ends = [-1.0, 0.0, 0.05, 0.95, 1.0, 2.0];
+        let bent: Vec<_> = ends.iter().map(|&value| soft.apply(value)).collect();
+        assert!(bent.iter().all(|&value| 0.0 < value && value < 1.0), "{:?}", bent);
+        assert!(bent.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", bent);
+        assert!(bent[2] > 0.05 && bent[3] < 0.95);
+        assert_eq!((ClipMethod::Hard.apply(-1.0), ClipMethod::Hard.apply(2.0)), (0.0, 1.0));
+        //So a soft-clipped texture has no pure black or white anywhere, bar whole-pixel ones.
+        let params: GeneratorParams = game::GameRng::seed_from_u64(1).gen();
+        let options = GenerateOptions {
+            clip: soft,
+            ..Default::default()
+        };
+        for generator in every_generator().into_iter().filter(|g| *g != Generators::PixelGrid) {
+            let texture = generate_with_options(
+                types::Area::new(16, 16), &generator, &params, &options,
+                &mut game::GameRng::seed_from_u64(2)
+            );
+            assert!(
+                texture.iter().flatten().all(|&value| 0.0 < value && value < 1.0),
+                "{}", generator.name()
+            );
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_anti_aliasing() {
+        //Run with cargo test --release -- --ignored --nocapture to see the timings.
+        let size = types::Area::new(512, 512);
+        let params: GeneratorParams = game::GameRng::seed_from_u64(0).gen();
+        let draw = |anti_aliasing| {
+            let options = GenerateOptions {
+                anti_aliasing,
+                no_roll: true,
+                ..Default::default()
+            };
+            let start = std::time::Instant::now();
+            let mut rng = game::GameRng::seed_from_u64(0);
+            let map =
+                generate_with_options(size, &Generators::Rangefrac, &params, &options, &mut rng);
+            (map, start.elapsed())
+        };
+        for _ in 0..3 {
+            let ((on, on_time), (off, off_time)) =
+                (draw(AntiAliasing::Always), draw(AntiAliasing::Off));
+            let differences = on.iter().flatten().zip(off.iter().flatten());
+            let largest = differences.fold(0.0, |largest, (a, b)| f64::max(largest, (a - b).abs()));
+            println!("on: {:?}, off: {:?} (differing by up to {})", on_time, off_time, largest);
+        }
     }
 }
@@ -0,0 +1,119 @@
+/*
+
+Copyright ©2021 Amane Katagiri
+Copyright ©1999 Mars Saxman
+All Rights Reserved
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, write to the Free Software
+Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
+
+*/
+
+use super::super::types;
+use super::super::rng::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PixelGridParams {
+    //How many one-pixel lines run across the image, and how many run down it. 0 draws none.
+    pub rows: usize,
+    pub columns: usize,
+}
+impl Default for PixelGridParams {
+    fn default() -> Self {
+        //An eight by eight grid of cells.
+        PixelGridParams {
+            rows: 8,
+            columns: 8,
+        }
+    }
+}
+impl Distribution<PixelGridParams> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> PixelGridParams {
+        PixelGridParams {
+            rows: rng.gen_range(2..=16),
+            columns: rng.gen_range(2..=16),
+        }
+    }
+}
+
+impl super::Generator for PixelGridParams {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        /*
+        Generator space doesn't say which pixel a point falls on, so the best
+        we can do is lay the grid over a nominal square of a thousand pixels.
+        Images only ever get the grid through call_absolute_generator.
+        */
+        let at = |v: f64| ((v.rem_euclid(1.0) * 1000.0) as usize).min(999);
+        generate(types::PixelPoint::new(at(x), at(y)), types::Area::new(1000, 1000), self)
+    }
+    fn is_seamless(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::PixelGrid).is_seamless
+    }
+    fn is_anti_aliased(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::PixelGrid).is_anti_aliased
+    }
+}
+
+pub fn generate(pixel: types::PixelPoint, size: types::Area, params: &PixelGridParams) -> f64 {
+    /*
+    Unlike the other generators, this one works on whole pixels, so every
+    line is exactly one pixel wide at any image size. The lines are spread
+    as evenly as whole pixels allow: with 4 rows in a 16 pixel high image
+    they are on rows 0, 4, 8 and 12.
+    */
+    if on_line(pixel.y, params.rows, size.height) || on_line(pixel.x, params.columns, size.width) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn on_line(at: usize, count: usize, length: usize) -> bool {
+    //Line i starts on the first pixel of the i'th of count equal runs across the length.
+    count > 0 && (at == 0 || at * count / length != (at - 1) * count / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::game;
+    use super::super::{generate_with_options, GeneratorParams, Generators};
+    use super::super::super::rng::SeedableRng;
+
+    #[test]
+    fn lines_land_on_exact_rows() {
+        let params = GeneratorParams {
+            pixelgrid: PixelGridParams {
+                rows: 4,
+                columns: 0,
+            },
+            ..Default::default()
+        };
+        //Whatever the rng, the grid isn't rolled.
+        for seed in 0..4 {
+            let map = generate_with_options(
+                types::Area::new(8, 16), &Generators::PixelGrid, &params, &Default::default(),
+                &mut game::GameRng::seed_from_u64(seed)
+            );
+            for (y, row) in map.iter().enumerate() {
+                let expected = if y % 4 == 0 {1.0} else {0.0};
+                assert!(row.iter().all(|&value| value == expected), "row {}: {:?}", y, row);
+            }
+        }
+    }
+}
@@ -20,10 +20,12 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 
 */
 
-use rand::{
+use super::super::game;
+use super::super::rng::{
     distributions::{Distribution, Standard},
-    Rng,
+    Rng, SeedableRng,
 };
+use std::sync::OnceLock;
 
 #[derive(Debug)]
 #[derive(Default)]
@@ -57,25 +59,113 @@ impl BoundingBox {
     }
 }
 
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RangefracKernel {
+    //Weigh the four nearest values by how close they are, in a cone; a little faceted.
+    #[default]
+    Cone,
+    //Blend the four nearest values along each axis in turn.
+    Bilinear,
+    //Fit a Catmull-Rom curve through the sixteen nearest values; the smoothest of the three.
+    Bicubic,
+}
+impl RangefracKernel {
+    fn weights(&self, t: f64) -> [f64; 4] {
+        //How much each of the values one before, at, one after and two after t counts.
+        match self {
+            RangefracKernel::Bicubic => {
+                let (t2, t3) = (t * t, t * t * t);
+                [
+                    (-t3 + 2.0 * t2 - t) / 2.0,
+                    (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+                    (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+                    (t3 - t2) / 2.0,
+                ]
+            },
+            _ => [0.0, 1.0 - t, t, 0.0],
+        }
+    }
+}
+
+type ValueMatrix = [[f64; RangefracParams::VALMATRIX_SIZE]; RangefracParams::VALMATRIX_SIZE];
+
 #[derive(Debug)]
 pub struct RangefracParams {
-    data: Box<[[f64; RangefracParams::VALMATRIX_SIZE]; RangefracParams::VALMATRIX_SIZE]>,
+    /*
+    The matrix is big and slow to fill, so params that only might be used,
+    like those in a default GeneratorParams, leave it empty and fill it from
+    the seed and coarse grid the first time a texture is drawn from them.
+    */
+    data: OnceLock<Box<ValueMatrix>>,
+    seed: u64,
+    //The coarsest values, if they were fixed instead of picked from the seed.
+    coarse_grid: Option<[f64; 4]>,
+    //How the matrix is scaled up to the whole tile.
+    kernel: RangefracKernel,
 }
 impl RangefracParams {
     const VALMATRIX_SCALE: u32 = 8;
     const VALMATRIX_SIZE: usize = 1 << RangefracParams::VALMATRIX_SCALE;
-}
-impl Default for RangefracParams {
-    fn default() -> Self {
+    const CORNER_STEP: usize = RangefracParams::VALMATRIX_SIZE / 2;
+
+    pub fn with_coarse_grid(
+        seed: u64, top_left: f64, top_middle: f64, middle_left: f64, centre: f64
+    ) -> Result<Self, String> {
+        /*
+        Fix the first, coarsest values instead of picking them at random.
+        These are the extremes of the image, so they anchor its overall tone;
+        the rest of the matrix is still filled in from the seed between them.
+        The matrix wraps around, so its coarsest grid is two by two, not the
+        four corners of the tile: top left is the point at the origin, top
+        middle and middle left are half a matrix away along x and along y,
+        and the centre is half a matrix away along both.
+        Nothing is filled in here, so these params stay as small as any
+        others until they are drawn with.
+        */
+        let values = [top_left, top_middle, middle_left, centre];
+        if !values.iter().all(|value| (0.0..=1.0).contains(value)) {
+            return Err("must be 0.0 <= top_left/top_middle/middle_left/centre <= 1.0".to_string());
+        }
+        Ok(RangefracParams {
+            coarse_grid: Some(values),
+            ..RangefracParams::lazy(seed)
+        })
+    }
+
+    pub fn from_seed(seed: u64) -> Self {
+        /*
+        Build the whole matrix from one number. The same seed always gives
+        the same fractal, so the seed is enough to keep a fractal around.
+        This draws from its own generator, not the game's shared one.
+        */
+        let params = RangefracParams::lazy(seed);
+        params.matrix();
+        params
+    }
+
+    fn lazy(seed: u64) -> Self {
         RangefracParams {
-            data: Box::new(
-                [[0.0; RangefracParams::VALMATRIX_SIZE]; RangefracParams::VALMATRIX_SIZE],
-            ),
+            data: OnceLock::new(),
+            seed,
+            coarse_grid: None,
+            kernel: Default::default(),
         }
     }
-}
-impl Distribution<RangefracParams> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> RangefracParams {
+    pub fn set_kernel(&mut self, kernel: RangefracKernel) {
+        self.kernel = kernel;
+    }
+    fn matrix(&self) -> &ValueMatrix {
+        self.data.get_or_init(
+            || RangefracParams::fill(&mut game::GameRng::seed_from_u64(self.seed), self.coarse_grid)
+        )
+    }
+
+    fn fill<R: Rng + ?Sized>(rng: &mut R, coarse_grid: Option<[f64; 4]>) -> Box<ValueMatrix> {
         /*
         Walk through the matrix.
         For each point, search its neighbors. For each neighboring point
@@ -87,6 +177,16 @@ impl Distribution<RangefracParams> for Standard {
             Box::new([[0_i32; RangefracParams::VALMATRIX_SIZE]; RangefracParams::VALMATRIX_SIZE]);
         let mut data =
             Box::new([[0.0; RangefracParams::VALMATRIX_SIZE]; RangefracParams::VALMATRIX_SIZE]);
+        //Seed the coarsest grid if we were given fixed values for it.
+        if let Some([top_left, top_middle, middle_left, centre]) = coarse_grid {
+            let far = RangefracParams::CORNER_STEP;
+            let coarsest =
+                [(0, 0, top_left), (far, 0, top_middle), (0, far, middle_left), (far, far, centre)];
+            for &(x, y, val) in &coarsest {
+                data[x][y] = val;
+                level[x][y] = far as i32;
+            }
+        }
 
         for step in 1..=RangefracParams::VALMATRIX_SCALE {
             let step = (2_usize).pow(RangefracParams::VALMATRIX_SCALE - step);
@@ -116,12 +216,13 @@ impl Distribution<RangefracParams> for Standard {
                             //Bottom right
                             (xi + step, yi + step),
                         ].iter().filter(|p| level[wrap_x(p.0)][wrap_y(p.1)] > step)
-                            .map(|p| data[wrap_x(p.0)][wrap_y(p.1)]).collect();
+                            .map(|p| data[wrap_x(p.0)][wrap_y(p.1)])
+                            .filter(|v: &f64| !v.is_nan()).collect();
                         let max = if !local_values.is_empty() {
-                            local_values.iter().fold(f64::NAN, |m, v| v.max(m))
+                            local_values.iter().fold(f64::NEG_INFINITY, |m, v| v.max(m))
                         } else {0.0};
                         let min = if !local_values.is_empty() {
-                            local_values.iter().fold(f64::NAN, |m, v| v.min(m))
+                            local_values.iter().fold(f64::INFINITY, |m, v| v.min(m))
                         } else {1.0};
                         let val = if min != max {
                             if min > max {
@@ -148,9 +249,68 @@ impl Distribution<RangefracParams> for Standard {
                 }
             }
         }
-        RangefracParams {
-            data
-        }
+        data
+    }
+}
+impl Default for RangefracParams {
+    fn default() -> Self {
+        /*
+        An all-zero matrix is flat, so fill it from a fixed seed instead,
+        the same one as from_seed(0); but only once it is actually used.
+        */
+        RangefracParams::lazy(0)
+    }
+}
+impl Distribution<RangefracParams> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> RangefracParams {
+        //Only the seed is drawn here; the matrix is filled from it if the texture is ever drawn.
+        RangefracParams::lazy(rng.gen())
+    }
+}
+
+/*
+The matrix is far too big for serde to handle as an array, and it is
+always filled from the seed and the fixed coarse grid anyway, so the params are
+written out as just those and their kernel.
+*/
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RangefracData {
+    kernel: RangefracKernel,
+    seed: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    coarse_grid: Option<[f64; 4]>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for RangefracParams {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(
+            &RangefracData {
+                kernel: self.kernel,
+                seed: self.seed,
+                coarse_grid: self.coarse_grid,
+            },
+            serializer,
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for RangefracParams {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data: RangefracData = serde::Deserialize::deserialize(deserializer)?;
+        //A coarse grid read in is held to the same range as one passed to with_coarse_grid.
+        let params = match data.coarse_grid {
+            Some([top_left, top_middle, middle_left, centre]) => RangefracParams::with_coarse_grid(
+                data.seed, top_left, top_middle, middle_left, centre
+            ).map_err(serde::de::Error::custom)?,
+            None => RangefracParams::lazy(data.seed),
+        };
+        Ok(RangefracParams {
+            kernel: data.kernel,
+            ..params
+        })
     }
 }
 
@@ -160,7 +320,26 @@ struct LocalParam {
     weight: f64,
 }
 
+impl super::Generator for RangefracParams {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        generate(super::GeneratorPoint::new(x, y), self)
+    }
+    fn is_seamless(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Rangefrac).is_seamless
+    }
+    fn is_anti_aliased(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Rangefrac).is_anti_aliased
+    }
+}
+
 pub fn generate(pixel: super::GeneratorPoint, params: &RangefracParams) -> f64 {
+    match params.kernel {
+        RangefracKernel::Cone => generate_cone(pixel, params),
+        _ => generate_separable(pixel, params),
+    }
+}
+
+fn generate_cone(pixel: super::GeneratorPoint, params: &RangefracParams) -> f64 {
     /*
     Locate the closest values to this one in the value
     array. Then use a proportional average based on distance
@@ -187,7 +366,7 @@ pub fn generate(pixel: super::GeneratorPoint, params: &RangefracParams) -> f64 {
         //BOTRIGHT
         (bound.bottom_right.x, bound.bottom_right.y),
     ].iter().map(|p| LocalParam {
-        value: params.data[wrap_x(p.0)][wrap_y(p.1)],
+        value: params.matrix()[wrap_x(p.0)][wrap_y(p.1)],
         weight: calc_weight(p.0, p.1, pixel)
     }).collect();
     let total_sum = local_params.iter().map(|v| v.value * v.weight).fold(0.0, |sum, x| sum + x);
@@ -195,6 +374,29 @@ pub fn generate(pixel: super::GeneratorPoint, params: &RangefracParams) -> f64 {
     total_sum / total_weight
 }
 
+fn generate_separable(pixel: super::GeneratorPoint, params: &RangefracParams) -> f64 {
+    /*
+    Scale the matrix up one axis at a time: the kernel gives a weight to
+    each of the four columns and four rows around the point, and each value
+    counts by its column's weight times its row's. The weights along each
+    axis already add up to one, so there is nothing to divide out.
+    */
+    let x = pixel.x * RangefracParams::VALMATRIX_SIZE as f64;
+    let y = pixel.y * RangefracParams::VALMATRIX_SIZE as f64;
+    let (left, top) = (x.floor(), y.floor());
+    let column_weights = params.kernel.weights(x - left);
+    let row_weights = params.kernel.weights(y - top);
+    let (left, top) = (left as i32 - 1, top as i32 - 1);
+    let matrix = params.matrix();
+    row_weights.iter().zip(top..).map(
+        |(row_weight, row)| column_weights.iter().zip(left..).map(
+            |(column_weight, column)| {
+                matrix[wrap_x(column)][wrap_y(row)] * column_weight * row_weight
+            }
+        ).sum::<f64>()
+    ).sum()
+}
+
 fn calc_weight(matrix_width: i32, matrix_height: i32, pixel: super::GeneratorPoint) -> f64 {
     f64::max(
         0.0,
@@ -217,3 +419,67 @@ fn wrap(coord: i32) -> usize {
         ) as usize,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::GeneratorPoint;
+    use super::*;
+
+    #[test]
+    fn fixed_values_land_on_the_coarsest_grid() {
+        let params = RangefracParams::with_coarse_grid(1, 0.1, 0.2, 0.3, 0.4).unwrap();
+        let (matrix, half) = (params.matrix(), RangefracParams::CORNER_STEP);
+        //The matrix is indexed x first.
+        assert_eq!(
+            [matrix[0][0], matrix[half][0], matrix[0][half], matrix[half][half]],
+            [0.1, 0.2, 0.3, 0.4]
+        );
+        for &bad in &[f64::NAN, -0.1, 1.5] {
+            assert!(RangefracParams::with_coarse_grid(1, 0.1, bad, 0.3, 0.4).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn coarse_grids_out_of_range_are_not_read() {
+        let read = |grid| serde_json::from_str::<RangefracParams>(
+            &format!(r#"{{"kernel":"Cone","seed":1,"coarse_grid":{}}}"#, grid)
+        );
+        assert!(read("[0.1,0.2,0.3,0.4]").is_ok());
+        assert!(read("[0.1,2.0,0.3,0.4]").is_err());
+    }
+
+    #[test]
+    fn bicubic_is_smoother_than_cone() {
+        /*
+        The sharpest bend along a few rows, sampled far finer than the matrix.
+        A crease only flattens out in proportion to the step between samples,
+        while a smooth curve flattens out with the square of it.
+        */
+        let bend = |kernel| {
+            let mut params = RangefracParams::from_seed(1);
+            params.set_kernel(kernel);
+            let samples = 32 * RangefracParams::VALMATRIX_SIZE;
+            (0..8).map(|row| {
+                let y = row as f64 / 8.0;
+                let values: Vec<f64> = (0..samples).map(
+                    |x| generate(GeneratorPoint::new(x as f64 / samples as f64, y), &params)
+                ).collect();
+                values.windows(3).map(|v| (v[0] - 2.0 * v[1] + v[2]).abs()).fold(0.0, f64::max)
+            }).fold(0.0, f64::max)
+        };
+        let (cone, bicubic) = (bend(RangefracKernel::Cone), bend(RangefracKernel::Bicubic));
+        assert!(bicubic < cone, "bicubic bends by {}, cone by {}", bicubic, cone);
+    }
+
+    #[test]
+    fn the_same_seed_fills_the_same_matrix() {
+        let values = |seed| RangefracParams::from_seed(seed).matrix().iter().flatten().copied()
+            .collect::<Vec<f64>>();
+        assert_eq!(values(5), values(5));
+        assert_ne!(values(5), values(6));
+        //The game's own rng is left out of it.
+        let drawn = game::with_seed(1, || values(5));
+        assert_eq!(drawn, values(5));
+    }
+}
@@ -20,16 +20,16 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 
 */
 
-use rand::{
+use super::super::rng::{
     distributions::{Distribution, Standard},
     Rng,
 };
 
 #[derive(Debug)]
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum SinePositivizingMethods {
     #[default]
-    DEFAULT,
     CompressMethod,
     TruncateMethod,
     AbsoluteMethod,
@@ -49,9 +49,9 @@ impl Distribution<SinePositivizingMethods> for Standard {
 
 #[derive(Debug)]
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TwirlMethods {
     #[default]
-    DEFAULT,
     NoneMethod,
     CurveMethod,
     SineMethod,
@@ -69,6 +69,7 @@ impl Distribution<TwirlMethods> for Standard {
 
 #[derive(Debug)]
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Twirl {
     base: f64,
     speed: f64,
@@ -83,7 +84,7 @@ impl Distribution<Twirl> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Twirl {
         let mut twirl = Twirl {
             base: rng.gen_range(0.0..=std::f64::consts::PI),
-            method: rand::random(),
+            method: rng.gen(),
             ..Default::default()
         };
         match twirl.method {
@@ -95,14 +96,14 @@ impl Distribution<Twirl> for Standard {
                 twirl.speed = rng.gen_range(-Twirl::MAX_TWIRL..=Twirl::MAX_TWIRL);
                 twirl.amp = rng.gen_range(-Twirl::MAX_SINEAMP..=Twirl::MAX_SINEAMP);
             },
-            _ => {},
+            TwirlMethods::NoneMethod => {},
         };
         twirl
     }
 }
 
 #[derive(Debug)]
-#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Floret {
     sinepos_method: SinePositivizingMethods,
     backward: bool,
@@ -110,14 +111,26 @@ pub struct Floret {
     spine_radius: f64,
     twirl: Twirl,
 }
+impl Default for Floret {
+    fn default() -> Self {
+        //A handful of gentle, untwirled spines.
+        Floret {
+            sinepos_method: Default::default(),
+            backward: false,
+            spines: 5,
+            spine_radius: 0.1,
+            twirl: Default::default(),
+        }
+    }
+}
 impl Distribution<Floret> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Floret {
         let mut floret = Floret{
-            sinepos_method: rand::random(),
+            sinepos_method: rng.gen(),
             backward: rng.gen_range(0..2) == 0,
             spines: rng.gen_range(0..=15) + 1,
-            spine_radius: rng.gen_range(0.0..=0.5),
-            twirl: rand::random(),
+            spine_radius: rng.gen_range(0.0..=SpinflakeParams::MAX_SPINE_RADIUS),
+            twirl: rng.gen(),
         };
         if let SinePositivizingMethods::AbsoluteMethod = floret.sinepos_method {
             if floret.spines % 2 == 1 {
@@ -129,52 +142,111 @@ impl Distribution<Floret> for Standard {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SpinflakeParams {
     origin: super::GeneratorPoint,
     radius: f64,
     squish: f64,
     twist: f64,
+    /*
+    The direction the squish pulls in. Left unset, it follows the twist,
+    so the florets and the squish turn together.
+    */
+    squish_angle: Option<f64>,
     average_florets: bool,
+    /*
+    Turn the whole spinflake into its own negative.
+    Each floret can already run backward, which flips how its spines bulge
+    out of the edge; this flips the finished shape instead, inside and out.
+    */
+    invert: bool,
+    /*
+    Every floret pushes the edge out by up to its spine_radius, on top of
+    the radius, and the pushes add up. Three florets with long spines around
+    a large radius reach more than twice across the tile, so nearly every
+    pixel sits deep inside the shape and comes out close to white. With
+    this set, the spines are scaled down together so that their total reach
+    is no more than one floret's longest, keeping their shapes but not their
+    sum. A radius near 1.0 still fills most of the tile on its own; only
+    the spines are reined in. Averaging the florets also tames the sum, but
+    it shrinks the radius along with them.
+    */
+    normalize_spines: bool,
     layer: Vec<Floret>,
 }
 impl SpinflakeParams {
     const MAX_FLORETS: usize = 3;
+    const MIN_EDGEDIST: f64 = 1.0e-6;
+    const MAX_SPINE_RADIUS: f64 = 0.5;
+
+    pub fn set_origin(&mut self, origin: super::GeneratorPoint) {
+        self.origin = origin;
+    }
+    pub fn set_invert(&mut self, invert: bool) {
+        self.invert = invert;
+    }
+    pub fn set_squish_angle(&mut self, squish_angle: Option<f64>) {
+        self.squish_angle = squish_angle;
+    }
+    pub fn set_normalize_spines(&mut self, normalize_spines: bool) {
+        self.normalize_spines = normalize_spines;
+    }
+    fn spine_scale(&self) -> f64 {
+        let total: f64 = self.layer.iter().map(|floret| floret.spine_radius).sum();
+        if self.normalize_spines && total > SpinflakeParams::MAX_SPINE_RADIUS {
+            SpinflakeParams::MAX_SPINE_RADIUS / total
+        } else {
+            1.0
+        }
+    }
 }
 impl Default for SpinflakeParams {
     fn default() -> Self {
         SpinflakeParams {
             layer: (0..1).map(|_| Default::default()).collect(),
-            origin: Default::default(),
-            radius: Default::default(),
-            squish: Default::default(),
+            origin: super::GeneratorPoint::new(0.5, 0.5),
+            radius: 0.25,
+            squish: 1.0,
             twist: Default::default(),
+            squish_angle: None,
             average_florets: Default::default(),
+            invert: false,
+            normalize_spines: false,
         }
     }
 }
 impl Distribution<SpinflakeParams> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> SpinflakeParams {
         SpinflakeParams {
-            origin: rand::random(),
+            origin: rng.gen(),
             radius: rng.gen_range(0.0..=1.0),
             squish: rng.gen_range(0.0..=2.75) * 0.25,
             twist: rng.gen_range(0.0..=std::f64::consts::PI),
+            squish_angle: None,
             average_florets: rng.gen_range(0..2) == 0,
+            invert: false,
+            normalize_spines: false,
             layer: (0..rng.gen_range(0..=(SpinflakeParams::MAX_FLORETS as i32)) + 1)
-                .map(|_| rand::random()).collect(),
+                .map(|_| rng.gen()).collect(),
         }
     }
 }
 
-pub fn generate(pixel: super::GeneratorPoint, params: &SpinflakeParams) -> f64 {
-    let val = vtiledpoint(pixel.x, pixel.y, params);
-    if pixel.x > 0.5 {
-        let farpoint = vtiledpoint(pixel.x - 1.0, pixel.y, params);
-        let farweight = (pixel.x - 0.5) * 2.0;
-        let weight = 1.0 - farweight;
-        return (val * weight) + (farpoint * farweight);
+impl super::Generator for SpinflakeParams {
+    fn sample_point(&self, x: f64, y: f64) -> f64 {
+        generate(super::GeneratorPoint::new(x, y), self)
+    }
+    fn is_seamless(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Spinflake).is_seamless
+    }
+    fn is_anti_aliased(&self) -> bool {
+        super::GeneratorProperty::get(&super::Generators::Spinflake).is_anti_aliased
     }
-    val
+}
+
+pub fn generate(pixel: super::GeneratorPoint, params: &SpinflakeParams) -> f64 {
+    let val = super::tiled_point(pixel.x, pixel.y, |x, y| rawpoint(x, y, params));
+    if params.invert {1.0 - val} else {val}
 }
 
 fn chopsin(theta: f64, params: &Floret) -> f64 {
@@ -188,7 +260,6 @@ fn chopsin(theta: f64, params: &Floret) -> f64 {
             let theta = if theta < 0.0 {theta + (std::f64::consts::PI / 2.0)} else {theta};
             theta.sin()
         },
-        _ => out,
     };
     if params.backward {
         return 1.0 - out;
@@ -196,17 +267,6 @@ fn chopsin(theta: f64, params: &Floret) -> f64 {
     out
 }
 
-fn vtiledpoint(x: f64, y: f64, params: &SpinflakeParams) -> f64 {
-    let point = rawpoint(x, y, params);
-    if y > 0.5 {
-        let farpoint = rawpoint(x, y - 1.0, params);
-        let farweight = (y - 0.5) * 2.0;
-        let weight = 1.0 - farweight;
-        return (point * weight) + (farpoint * farweight);
-    }
-    point
-}
-
 fn rawpoint(x: f64, y: f64, params: &SpinflakeParams) -> f64 {
     /*
     Rotate the point around our origin. This lets the squashed bulge-points on
@@ -216,24 +276,45 @@ fn rawpoint(x: f64, y: f64, params: &SpinflakeParams) -> f64 {
     let x = x - params.origin.x;
     let y = y - params.origin.y;
 
-    let hypangle = (y / x).atan() + params.twist;
-    let origindist = x.hypot(y);
-
-    let x = hypangle.cos() * origindist;
-    let y = hypangle.sin() * origindist;
-    //Calculate the distance from the origin to this point. Again.
-    let origindist = (x * params.squish).hypot(y / params.squish);
+    let rotate = |angle: f64| {
+        let (hypangle, origindist) = super::angle_and_dist(x, y);
+        let hypangle = hypangle + angle;
+        (hypangle.cos() * origindist, hypangle.sin() * origindist)
+    };
+    let (x, y) = rotate(params.twist);
+    /*
+    Calculate the distance from the origin to this point. Again.
+    The squish is measured along its own axes, which are the twisted ones
+    unless it has an angle of its own.
+    */
+    let (squishx, squishy) = match params.squish_angle {
+        Some(angle) => rotate(angle),
+        None => (x, y),
+    };
+    let origindist = (squishx * params.squish).hypot(squishy / params.squish);
     //If we are at the origin, there is no need to do the computations.
     if origindist != 0.0 {
         //The edge is (currently) a circle some radius units away.
-        //Compute the angle this point represents to the origin.
-        let pointangle = (y / x).atan();
+        /*
+        Compute the angle this point represents to the origin.
+        It goes all the way round: with only half a turn, the left half of
+        the point's surroundings repeated the right half, so florets whose
+        spines don't repeat every half turn (an odd number of them, or the
+        sawblade) met themselves along a seam down the middle.
+        */
+        let (pointangle, _) = super::angle_and_dist(x, y);
+        let spine_scale = params.spine_scale();
         let mut edgedist = params.radius;
         for layer in &params.layer {
-            edgedist += calcwave(pointangle, origindist, layer);
+            edgedist += calcwave(pointangle, origindist, layer) * spine_scale;
         }
         let edgedist =
             if params.average_florets {edgedist / (params.layer.len() as f64)} else {edgedist};
+        /*
+        A zero radius with florets that add nothing at this angle would put the
+        edge right on the origin and divide by zero below. Keep it a hair away.
+        */
+        let edgedist = f64::max(edgedist, SpinflakeParams::MIN_EDGEDIST);
         //Our return value is the distance from the edge, proportionate
         //to the distance from the origin to the edge.
         let proportiondist = (edgedist - origindist) / edgedist;
@@ -261,7 +342,115 @@ fn calcwave(theta: f64, dist: f64, params: &Floret) -> f64 {
             + (dist * (params.twirl.speed + (dist * params.twirl.amp))),
         TwirlMethods::SineMethod => (theta * (params.spines as f64) + params.twirl.base)
             + ((dist * params.twirl.speed).sin() * (params.twirl.amp + (dist * params.twirl.amp))),
-        _ => theta * (params.spines as f64) + params.twirl.base,
+        TwirlMethods::NoneMethod => theta * (params.spines as f64) + params.twirl.base,
     };
     chopsin(cosparam, params) * params.spine_radius
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::game;
+    use super::super::GeneratorPoint;
+    use super::*;
+    use super::super::super::rng::SeedableRng;
+
+    #[test]
+    fn a_zero_radius_with_flat_florets_is_finite() {
+        //No radius and no spines put the edge right on the origin at every angle.
+        let params = SpinflakeParams {
+            radius: 0.0,
+            layer: (0..3).map(|_| Floret {spine_radius: 0.0, ..Default::default()}).collect(),
+            ..Default::default()
+        };
+        for x in 0..=16 {
+            for y in 0..=16 {
+                let point = GeneratorPoint::new(x as f64 / 16.0, y as f64 / 16.0);
+                let value = generate(point, &params);
+                assert!(value.is_finite(), "{} at {:?}", value, point);
+            }
+        }
+    }
+
+    #[test]
+    fn inverted_spinflakes_are_one_minus_the_plain_ones() {
+        for seed in 0..8 {
+            let plain: SpinflakeParams = game::GameRng::seed_from_u64(seed).gen();
+            let mut inverted: SpinflakeParams = game::GameRng::seed_from_u64(seed).gen();
+            inverted.set_invert(true);
+            for i in 0..16 * 16 {
+                let point = GeneratorPoint::new((i % 16) as f64 / 16.0, (i / 16) as f64 / 16.0);
+                assert_eq!(generate(point, &inverted), 1.0 - generate(point, &plain));
+            }
+        }
+    }
+
+    #[test]
+    fn the_squish_angle_turns_the_ellipse_but_not_the_florets() {
+        let spiky = || vec![Floret {spines: 5, spine_radius: 0.2, ..Default::default()}];
+        let flake = |squish, squish_angle, layer| SpinflakeParams {
+            squish,
+            twist: 0.3,
+            squish_angle,
+            layer,
+            ..Default::default()
+        };
+        //Points around the origin, each with the point a turn of the squish takes it back to.
+        let turn: f64 = 0.9;
+        let points = (0..64).map(|i| {
+            let (angle, dist) = (i as f64 * 0.7, 0.05 + (i % 8) as f64 * 0.03);
+            let (x, y) = (angle.cos() * dist, angle.sin() * dist);
+            let back = (x * turn.cos() + y * turn.sin(), y * turn.cos() - x * turn.sin());
+            ((x + 0.5, y + 0.5), (back.0 + 0.5, back.1 + 0.5))
+        });
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        //Without a squish, turning it changes nothing: the spines stay where the twist put them.
+        let (plain, turned) = (flake(1.0, None, spiky()), flake(1.0, Some(1.2), spiky()));
+        for ((x, y), _) in points.clone() {
+            assert!(close(rawpoint(x, y, &turned), rawpoint(x, y, &plain)), "at {}, {}", x, y);
+        }
+        //Without spines the edge is a plain ellipse, and turning the squish turns it round.
+        let ellipse = flake(2.0, Some(0.4), vec![]);
+        let turned = flake(2.0, Some(0.4 + turn), vec![]);
+        for ((x, y), (back_x, back_y)) in points {
+            let (before, after) = (rawpoint(x, y, &ellipse), rawpoint(back_x, back_y, &turned));
+            assert!(close(before, after), "{} turned to {} at {}, {}", before, after, x, y);
+        }
+    }
+
+    #[test]
+    fn the_exact_origin_is_finite() {
+        for seed in 0..8 {
+            let params: SpinflakeParams = game::GameRng::seed_from_u64(seed).gen();
+            let value = rawpoint(params.origin.x, params.origin.y, &params);
+            assert!(value.is_finite(), "seed {}: {}", seed, value);
+        }
+    }
+
+    #[test]
+    fn normalized_long_spines_are_not_mostly_white() {
+        let flake = |normalize_spines| SpinflakeParams {
+            normalize_spines,
+            layer: (0..SpinflakeParams::MAX_FLORETS as i32).map(|i| Floret {
+                spines: 3 + i * 2,
+                spine_radius: SpinflakeParams::MAX_SPINE_RADIUS,
+                ..Default::default()
+            }).collect(),
+            ..Default::default()
+        };
+        let values = |params: &SpinflakeParams| (0..32 * 32).map(|i| {
+            let point = GeneratorPoint::new((i % 32) as f64 / 32.0, (i / 32) as f64 / 32.0);
+            generate(point, params)
+        }).collect::<Vec<_>>();
+        let white = |values: &[f64]| {
+            values.iter().filter(|&&value| value > 0.9).count() as f64 / values.len() as f64
+        };
+        let (summed, normalized) = (values(&flake(false)), values(&flake(true)));
+        assert!(white(&normalized) < 0.5, "{} of it is white", white(&normalized));
+        assert!(white(&normalized) < white(&summed));
+        //Not flat either: the shape still runs from its edge right up to the middle.
+        let (low, high) = normalized.iter().fold((1.0, 0.0), |(low, high): (f64, f64), &value| {
+            (low.min(value), high.max(value))
+        });
+        assert!(high - low > 0.5, "only {}..{}", low, high);
+    }
+}
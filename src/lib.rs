@@ -22,31 +22,44 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 
 extern crate wasm_bindgen;
 
+pub mod blend;
 pub mod game;
 pub mod generators;
+pub mod quantize;
+pub mod rng;
+pub mod texture;
 pub mod types;
 
-use rand::{
-    distributions::{Distribution, Standard},
+use num_traits::Float;
+use rng::{
+    distributions::{uniform::SampleUniform, Distribution, Standard},
     Rng,
 };
+use std::convert::{TryFrom, TryInto};
+#[cfg(feature = "entropy")]
+use std::io::Write;
+#[cfg(feature = "entropy")]
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 
+/*
+A colour with straight channels from 0.0 to 1.0.
+The channel type defaults to PixelVal, which is what the generators and the
+compositing work in; Colour<f32> is there for handing colours on to graphics
+APIs that want single precision. Use cast to move between the two.
+*/
 #[derive(Debug, Default, Clone, Copy)]
-pub struct Colour {
-    pub red: types::PixelVal,
-    pub green: types::PixelVal,
-    pub blue: types::PixelVal,
-    pub alpha: types::PixelVal,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Colour<T = types::PixelVal> {
+    pub red: T,
+    pub green: T,
+    pub blue: T,
+    pub alpha: T,
 }
-impl Colour {
-    pub fn new(
-        red: types::PixelVal,
-        green: types::PixelVal,
-        blue: types::PixelVal,
-        alpha: types::PixelVal,
-    ) -> Self {
+impl<T: Float> Colour<T> {
+    pub fn new(red: T, green: T, blue: T, alpha: T) -> Self {
         Colour {
             red,
             green,
@@ -54,7 +67,22 @@ impl Colour {
             alpha,
         }
     }
-    pub fn scale(&self, factor: f64) -> Colour {
+    pub fn cast<U: Float>(&self) -> Colour<U> {
+        //Float to float conversions can't fail; at worst they round.
+        let cast = |c: T| U::from(c).unwrap();
+        Colour::new(
+            cast(self.red),
+            cast(self.green),
+            cast(self.blue),
+            cast(self.alpha),
+        )
+    }
+    pub fn scale(&self, factor: T) -> Colour<T> {
+        /*
+        Multiply every channel, alpha included, by the factor.
+        Nothing is clamped, so the result can leave the 0..1 range; use
+        scale_clamped when it has to stay a valid colour.
+        */
         Colour::new(
             self.red * factor,
             self.green * factor,
@@ -62,287 +90,2165 @@ impl Colour {
             self.alpha * factor,
         )
     }
+    pub fn scale_clamped(&self, factor: T) -> Colour<T> {
+        let clamp = |c: T| c.max(T::zero()).min(T::one());
+        let p = self.scale(factor);
+        Colour::new(clamp(p.red), clamp(p.green), clamp(p.blue), clamp(p.alpha))
+    }
+    pub fn lerp(&self, other: &Colour<T>, t: T) -> Colour<T> {
+        //Walk t of the way from this colour to the other, one channel at a time.
+        Colour::new(
+            self.red + (other.red - self.red) * t,
+            self.green + (other.green - self.green) * t,
+            self.blue + (other.blue - self.blue) * t,
+            self.alpha + (other.alpha - self.alpha) * t,
+        )
+    }
+    pub fn distance(&self, other: &Colour<T>) -> T {
+        //Straight-line distance between the two colours in RGB space. Alpha is ignored.
+        let (r, g, b) = (
+            self.red - other.red,
+            self.green - other.green,
+            self.blue - other.blue,
+        );
+        (r * r + g * g + b * b).sqrt()
+    }
+    pub fn same_rgb(&self, other: &Colour<T>) -> bool {
+        self.red == other.red && self.green == other.green && self.blue == other.blue
+    }
+    fn nudged(&self) -> Colour<T> {
+        //Move every channel a step towards the middle, so none of them can stay put.
+        let nudge_by = T::from(1.0 / 32.0).unwrap();
+        let half = T::from(0.5).unwrap();
+        let nudge = |c: T| if c > half { c - nudge_by } else { c + nudge_by };
+        Colour::new(
+            nudge(self.red),
+            nudge(self.green),
+            nudge(self.blue),
+            self.alpha,
+        )
+    }
+    pub fn is_in_range(&self) -> bool {
+        [self.red, self.green, self.blue, self.alpha]
+            .iter()
+            .all(|c| T::zero() <= *c && *c <= T::one())
+    }
+    pub fn apply_channel_gamma(&self, red: T, green: T, blue: T) -> Colour<T> {
+        /*
+        Raise each colour channel to its own gamma, for simple colour grading.
+        A gamma above 1.0 darkens that channel, below 1.0 lightens it, and 1.0
+        leaves it alone; black and full intensity always stay put. Channels
+        below zero are treated as zero. Alpha is left as it is.
+        */
+        let curve = |c: T, gamma: T| c.max(T::zero()).powf(gamma);
+        Colour::new(
+            curve(self.red, red),
+            curve(self.green, green),
+            curve(self.blue, blue),
+            self.alpha,
+        )
+    }
+    pub fn from_hsv(hue: T, saturation: T, value: T, alpha: T) -> Colour<T> {
+        /*
+        The other way round from to_hsv: hue as a fraction of a turn from
+        red, saturation and value from 0 to 1. A hue outside 0..1 simply
+        goes round the wheel again.
+        */
+        let six = T::from(6.0).unwrap();
+        let turn = (hue - hue.floor()) * six;
+        let sector = turn.floor();
+        let f = turn - sector;
+        let p = value * (T::one() - saturation);
+        let q = value * (T::one() - saturation * f);
+        let t = value * (T::one() - saturation * (T::one() - f));
+        //A hue a hair below a whole turn rounds up to six, which is red again.
+        let (red, green, blue) = match sector.to_i32().unwrap_or(0) % 6 {
+            0 => (value, t, p),
+            1 => (q, value, p),
+            2 => (p, value, t),
+            3 => (p, q, value),
+            4 => (t, p, value),
+            _ => (value, p, q),
+        };
+        Colour::new(red, green, blue, alpha)
+    }
+    pub fn to_hsv(&self) -> (T, T, T) {
+        /*
+        Convert to hue, saturation and value, all from 0 to 1.
+        The hue is the fraction of a turn round the colour wheel from red,
+        through green at 1/3 and blue at 2/3. Greys have no hue; we call it 0.
+        Alpha is ignored.
+        */
+        let max = self.red.max(self.green).max(self.blue);
+        let min = self.red.min(self.green).min(self.blue);
+        let chroma = max - min;
+        let six = T::from(6.0).unwrap();
+        let hue = if chroma <= T::zero() {
+            T::zero()
+        } else if max == self.red {
+            ((self.green - self.blue) / chroma / six + T::one()) % T::one()
+        } else if max == self.green {
+            ((self.blue - self.red) / chroma + T::from(2.0).unwrap()) / six
+        } else {
+            ((self.red - self.green) / chroma + T::from(4.0).unwrap()) / six
+        };
+        let saturation = if max > T::zero() {
+            chroma / max
+        } else {
+            T::zero()
+        };
+        (hue, saturation, max)
+    }
+    pub fn luminance(&self) -> T {
+        //Relative luminance with the Rec.709 primaries. Alpha is ignored.
+        T::from(0.2126).unwrap() * self.red
+            + T::from(0.7152).unwrap() * self.green
+            + T::from(0.0722).unwrap() * self.blue
+    }
+}
+impl Colour {
+    pub fn to_rgb8(&self) -> [u8; 3] {
+        [
+            types::to_chanval(self.red),
+            types::to_chanval(self.green),
+            types::to_chanval(self.blue),
+        ]
+    }
+}
+impl<T: Float + SampleUniform> Colour<T> {
+    #[cfg(feature = "entropy")]
+    pub fn random_opaque() -> Self {
+        /*
+        A random colour that is fully opaque, for places where the alpha is
+        actually used, such as colours handed on to other graphics code.
+        High alpha means high opacity here, just as in get_pixel_val.
+        */
+        Colour::random_opaque_with(&mut game::get_rng())
+    }
+    pub fn random_opaque_with<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        //Same as random_opaque, but drawn from the given rng.
+        Colour {
+            alpha: T::one(),
+            ..rng.gen()
+        }
+    }
 }
-impl Distribution<Colour> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Colour {
+impl<T: Float + SampleUniform> Distribution<Colour<T>> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Colour<T> {
+        /*
+        The alpha is left at 0 on purpose. A random colour mostly ends up as
+        the fore or back colour of a layer, and a layer's opacity comes from
+        its mask alone: get_pixel_val only ever looks at the red, green and
+        blue of those colours, so any alpha drawn here would be thrown away.
+        Use Colour::random_opaque for a colour whose alpha counts.
+        */
         Colour::new(
-            rng.gen_range(0.0..=1.0),
-            rng.gen_range(0.0..=1.0),
-            rng.gen_range(0.0..=1.0),
-            0.0,
+            rng.gen_range(T::zero()..=T::one()),
+            rng.gen_range(T::zero()..=T::one()),
+            rng.gen_range(T::zero()..=T::one()),
+            T::zero(),
         )
     }
 }
 
-#[derive(Debug, Default)]
-pub struct ColourPalette {
-    pub colours: Vec<Colour>,
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColourPalette<T = types::PixelVal> {
+    pub colours: Vec<Colour<T>>,
 }
-impl ColourPalette {
-    pub fn sample(&self) -> Result<Colour, String> {
+impl<T: Float + SampleUniform> ColourPalette<T> {
+    pub fn cast<U: Float>(&self) -> ColourPalette<U> {
+        ColourPalette {
+            colours: self.colours.iter().map(Colour::cast).collect(),
+        }
+    }
+    pub fn from_hex<S: AsRef<str>>(hex_colours: &[S]) -> Result<Self, String> {
+        /*
+        Build a palette from colours written the way CSS does, "#rrggbb" or
+        the short "#rgb", with or without the "#". The colours are opaque.
+        */
+        let channel = |digits: &str| {
+            u8::from_str_radix(digits, 16)
+                .ok()
+                .and_then(|byte| T::from(byte as f64 / 255.0))
+        };
+        let colours = hex_colours
+            .iter()
+            .map(|hex| {
+                let hex = hex.as_ref();
+                let digits = hex.trim_start_matches('#');
+                let long = match digits.len() {
+                    _ if !digits.is_ascii() => None,
+                    3 => Some(digits.chars().flat_map(|c| [c, c]).collect()),
+                    6 => Some(digits.to_string()),
+                    _ => None,
+                };
+                long.and_then(|long: String| {
+                    Some(Colour::new(
+                        channel(&long[0..2])?,
+                        channel(&long[2..4])?,
+                        channel(&long[4..6])?,
+                        T::one(),
+                    ))
+                })
+                .ok_or_else(|| format!("not a hex colour: {:?}", hex))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ColourPalette { colours })
+    }
+    pub fn dedup(&mut self, threshold: T) {
+        /*
+        Drop colours that are too close to one we already have.
+        We walk the palette in order and keep each colour only if it is at
+        least threshold away (see Colour::distance) from every colour kept
+        so far, so the first of a group of near-duplicates is the one left.
+        */
+        let mut kept: Vec<Colour<T>> = Vec::with_capacity(self.colours.len());
+        for colour in self.colours.drain(..) {
+            if kept.iter().all(|k| k.distance(&colour) >= threshold) {
+                kept.push(colour);
+            }
+        }
+        self.colours = kept;
+    }
+    #[cfg(feature = "entropy")]
+    pub fn sample(&self) -> Result<Colour<T>, String> {
+        self.sample_with(&mut game::get_rng())
+    }
+    pub fn sample_with<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<Colour<T>, String> {
         /*
         Pick a random pixel from this palette.
         If the palette is empty, create it from random values.
+        A palette of one colour is still that colour; the layers make their
+        gradients by nudging it (see ColourLayer::random).
         */
-        if self.colours.len() > 1 {
-            let mut rng = game::get_rng();
-
-            let c = &self.colours[rng.gen_range(0..self.colours.len())];
-            if 0.0 <= c.red
-                && c.red <= 1.0
-                && 0.0 <= c.green
-                && c.green <= 1.0
-                && 0.0 <= c.blue
-                && c.blue <= 1.0
-                && 0.0 <= c.alpha
-                && c.alpha <= 1.0
-            {
-                return Ok(Colour::new(c.red, c.green, c.blue, c.alpha));
+        if !self.colours.is_empty() {
+            let c = &self.colours[rng.gen_range(0..self.colours.len() as u64) as usize];
+            if c.is_in_range() {
+                return Ok(*c);
             }
             return Err("color values must be 0.0 <= r/g/b/a <= 1.0".to_string());
         }
-        Ok(rand::random())
+        Ok(rng.gen())
+    }
+}
+
+//How to draw one texture of a layer again, at any size.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct TextureSource {
+    generator: generators::Generators,
+    params: Arc<generators::GeneratorParams>,
+    //A second generator blended in, and the share of the first one in the blend.
+    mixed: Option<(
+        generators::Generators,
+        Arc<generators::GeneratorParams>,
+        f64,
+    )>,
+    //How far the texture is rolled around the tile, as a share of its size.
+    roll: generators::GeneratorPoint,
+}
+impl TextureSource {
+    fn generate(&self, size: types::Area) -> types::PixelMap {
+        match &self.mixed {
+            Some((second, second_params, weight)) => generators::generate_mix_at(
+                size,
+                self.roll,
+                (&self.generator, &self.params),
+                (second, second_params),
+                *weight,
+            )
+            .unwrap(),
+            None => generators::generate_at(size, self.roll, &self.generator, &self.params),
+        }
+    }
+    #[cfg(feature = "entropy")]
+    fn generate_row(&self, size: types::Area, y: usize) -> Vec<types::PixelVal> {
+        //Row y of what generate draws at this size.
+        match &self.mixed {
+            Some((second, second_params, weight)) => generators::generate_mix_row_at(
+                size,
+                self.roll,
+                (&self.generator, &self.params),
+                (second, second_params),
+                *weight,
+                y,
+            )
+            .unwrap(),
+            None => generators::generate_row_at(size, self.roll, &self.generator, &self.params, y),
+        }
     }
 }
 
+/*
+A layer is written out without its textures, which are big; they are drawn
+again from their sources when it is read back in, pixel for pixel the same.
+*/
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct ColourLayer {
     //The image layer, a reference to pixels.
+    #[cfg_attr(feature = "serde", serde(skip))]
     image: types::PixelMap,
     //The foreground colour, used for high image values.
     fore: Colour,
     //The background colour, used for low image values.
     back: Colour,
     //The mask image. If None, we use the image layer as its own mask.
+    #[cfg_attr(feature = "serde", serde(skip))]
     mask: Option<types::PixelMap>,
     //If the flag is true, we invert the mask.
     invert_mask: bool,
+    //Where the image and the mask came from, so they can be drawn again at another size.
+    image_source: TextureSource,
+    mask_source: Option<TextureSource>,
 }
 
-#[derive(Debug)]
-pub struct Jelatofish {
-    size: types::Area,
-    cutoff_threshold: types::PixelVal,
-    layers: Vec<ColourLayer>,
+//Everything one layer has to say about one pixel, for Jelatofish::render_with.
+#[derive(Debug, Clone, Copy)]
+pub struct LayerSample {
+    //The image value, which picks a colour between back (0.0) and fore (1.0).
+    pub image: types::PixelVal,
+    //How much the layer covers the pixel, with invert_mask already applied.
+    pub mask: types::PixelVal,
+    pub fore: Colour,
+    pub back: Colour,
 }
-impl Jelatofish {
-    const MAX_LAYERS: usize = 6;
-    const MIN_LAYERS: usize = 2;
 
-    const MAX_CUTOFF_THRESHOLD: f64 = 1.0 / 16.0;
+impl ColourLayer {
+    const MAX_COLOUR_TRIES: usize = 64;
 
-    pub fn random(
+    fn sample(&self, x: usize, y: usize) -> LayerSample {
+        let image = self.image[y][x];
+        let maskval = match &self.mask {
+            Some(mask) => mask[y][x],
+            None => image,
+        };
+        let mask = if self.invert_mask {
+            1.0 - maskval
+        } else {
+            maskval
+        };
+        LayerSample {
+            image,
+            mask,
+            fore: self.fore,
+            back: self.back,
+        }
+    }
+    fn pixel(&self, x: usize, y: usize) -> Colour {
+        /*
+        The colour this layer has on its own at one pixel, with its mask as
+        the alpha. This is the same pixel get_pixel_val merges.
+        */
+        let sample = self.sample(x, y);
+        Colour {
+            alpha: sample.mask,
+            ..sample.back.lerp(&sample.fore, sample.image)
+        }
+    }
+
+    fn random(
         size: types::Area,
-        colours: &ColourPalette,
-        layer_count: Option<usize>,
-        cutoff_threshold: Option<types::PixelVal>,
+        colours: &LayerColours,
+        depth: f64,
+        options: &LayerOptions,
     ) -> Result<Self, String> {
         /*
-        Create a series of layers which we will later use to generate
-        pixel data. These will contain the complete package of settings
-        used to calculate image values.
+        Now allocate random layers to use for the image and mask of this layer.
+        Half the time, we use the image as its own mask.
+        Half the time, we invert the mask.
+        Only the sources of the textures are picked here; the textures
+        themselves are left empty until draw fills them in.
+        Every choice is drawn from the one rng, in the same order every time.
         */
         let mut rng = game::get_rng();
-        let layer_count = match layer_count {
-            Some(x) if (Jelatofish::MIN_LAYERS..=Jelatofish::MAX_LAYERS).contains(&x) => x,
-            None => rng.gen_range(Jelatofish::MIN_LAYERS..=Jelatofish::MAX_LAYERS),
-            _ => {
-                return Err(format!(
-                    "must be {} <= layer_count <= {}",
-                    Jelatofish::MIN_LAYERS,
-                    Jelatofish::MAX_LAYERS,
-                ))
+        //Now pick some random colours to use as fore and back of gradients.
+        let back = colours.sample(depth, &mut rng)?;
+        //The fore and back colours should NEVER be equal.
+        //Keep picking random colours until they don't match.
+        let mut fore = None;
+        for _ in 0..ColourLayer::MAX_COLOUR_TRIES {
+            let colour = colours.sample(depth, &mut rng)?;
+            if !colour.same_rgb(&back) {
+                fore = Some(colour);
+                break;
             }
+        }
+        /*
+        A small palette may have nothing else to offer. Rather than give
+        up, use the colour anyway and nudge the back colour a little
+        towards mid-grey so that the gradient still has two ends.
+        */
+        let (fore, back) = match fore {
+            Some(fore) => (fore, back),
+            None => (back, back.nudged()),
         };
-        let cutoff_threshold = match cutoff_threshold {
-            Some(x) if x <= Jelatofish::MAX_CUTOFF_THRESHOLD => x,
-            None => rng.gen_range(0.0..=Jelatofish::MAX_CUTOFF_THRESHOLD),
-            _ => {
-                return Err(format!(
-                    "must be cutoff_threshold <= {}",
-                    Jelatofish::MAX_CUTOFF_THRESHOLD
-                ))
-            }
+        /*
+        The settings of each generator are fine detail. Where the generators
+        are centred is part of the overall composition, so that is picked
+        separately.
+        */
+        let LayerOptions {
+            shared_origin,
+            mixed,
+            generators: allowed,
+        } = options;
+        let (shared_origin, mixed) = (*shared_origin, *mixed);
+        //Pick a generator, from the allowed ones if the caller narrowed them down.
+        let pick = |rng: &mut game::GameRng| match allowed {
+            Some(allowed) => allowed[rng.gen_range(0..allowed.len() as u64) as usize],
+            None => rng.gen(),
         };
-
-        Ok(Jelatofish {
-            size,
-            cutoff_threshold,
-            layers: vec![0; layer_count]
-                .iter()
-                .map(|_| {
-                    /*
-                    Now allocate random layers to use for the image and mask of this layer.
-                    Half the time, we use the image as its own mask.
-                    Half the time, we invert the mask.
-                    */
-                    //Now pick some random colours to use as fore and back of gradients.
-                    let back = colours.sample().unwrap();
-                    //The fore and back colours should NEVER be equal.
-                    //Keep picking random colours until they don't match.
-                    let fore = loop {
-                        let fore = colours.sample().unwrap();
-                        if fore.red != back.red
-                            || fore.green != back.green
-                            || fore.blue != back.blue
-                        {
-                            break fore;
-                        }
-                    };
-                    let params: generators::GeneratorParams = rand::random();
-                    ColourLayer {
-                        image: generators::generate(size, &rand::random(), &params),
-                        //Flip a coin. If it lands heads-up, create another layer for use as a mask.
-                        mask: if game::maybe() {
-                            Some(generators::generate(size, &rand::random(), &params))
-                        } else {
-                            None
-                        },
-                        //Flip another coin. If it lands heads-up, set the flag so we invert this layer.
-                        invert_mask: game::maybe(),
-                        back,
-                        fore,
-                    }
-                })
-                .collect(),
-        })
-    }
-    pub fn get_pixel_val(&self, x: usize, y: usize) -> Result<Colour, String> {
+        let mut detail = game::in_detail(game::get_rng);
+        let mut params: generators::GeneratorParams = detail.gen();
+        params.set_origin(rng.gen());
         /*
-        Calculate one pixel.
-        We start with a black pixel.
-        Then we loop through all of the layers, calculating each one with its
-        mask. We then merge each layer's resulting pixel onto the out image.
-        Once we're done, we return the merged pixel.
-        We use alpha kind of backwards: high values mean high opacity, low values
-        mean low opacity.
+        If the caller wants every layer centred on the same point, move
+        the origins there and skip the random roll, which would otherwise
+        scatter the layers again. Points in generator space then land on
+        the same pixels in every texture, so the layers stay lined up.
         */
-        //Did we get valid parameters?
-        if x >= self.size.width && y >= self.size.height {
-            return Err(format!(
-                "must be x >= {} && y >= {}",
-                self.size.width, self.size.height
-            ));
+        let roll = |generator: &generators::Generators, rng: &mut game::GameRng| match shared_origin
+        {
+            Some(_) => generators::GeneratorPoint::new(0.0, 0.0),
+            None => generators::random_roll(size, generator, rng),
+        };
+        if let Some(origin) = shared_origin {
+            params.set_origin(origin);
         }
-        let mut outval: Colour = Default::default();
-        for layer in &self.layers {
-            //Get the image value for this pixel, for this layer.
-            let imageval = layer.image[x][y];
-            //Do we have a mask texture? If we do, calculate its value.
-            let maskval = match &layer.mask {
-                Some(mask) => mask[x][y],
-                None => layer.image[x][y],
-            };
-            //Are we supposed to invert the mask value we got?
-            let maskval = if layer.invert_mask {
-                1.0 - maskval
-            } else {
-                maskval
-            };
-            /*
-            Now we are ready. Calculate the image value for this layer.
-            We use the image value as the proportion of the distance between
-            two colours. We calculate this one channel at a time. This results
-            in a smooth gradient of colour from min to max.
-            */
-            let mut layerpixel = Colour {
-                red: imageval * (layer.fore.red - layer.back.red) + layer.back.red,
-                green: imageval * (layer.fore.green - layer.back.green) + layer.back.green,
-                blue: imageval * (layer.fore.blue - layer.back.blue) + layer.back.blue,
-                alpha: maskval,
-            };
-            /*
-            The image value for this layer is calculated.
-            But the image is more than just this layer: it is the merged
-            results of all the layers. So now we merge this value with
-            the existing value calculated from the previous layers.
-            We use the alpha channel to determine the proportion of blending.
-            The new layer goes behind the existing layers; we use the existing
-            alpha channel to determine what proportion of the new value shows
-            through.
-            */
-            outval.red = (outval.red * outval.alpha) + (layerpixel.red * (1.0 - outval.alpha));
-            outval.green =
-                (outval.green * outval.alpha) + (layerpixel.green * (1.0 - outval.alpha));
-            outval.blue = (outval.blue * outval.alpha) + (layerpixel.blue * (1.0 - outval.alpha));
-            /*
-            Add the alpha channels (representing opacity); if the result is greater
-            than 100% opacity, we just stop calculating (since no further layers
-            will produce visible data).
-            */
-            layerpixel.alpha *= 1.0 - outval.alpha;
-            if layerpixel.alpha + outval.alpha + self.cutoff_threshold >= 1.0 {
-                outval.alpha = 1.0;
-                /*
-                And now end the loop, because we've collected all the data we need.
-                Calculating pixels from any of the deeper layers would just be a waste of time.
-                */
-                break;
-            } else {
-                outval.alpha += layerpixel.alpha;
+        let image = if mixed {
+            //Blend this generator with a second one, in a random proportion.
+            let mut second: generators::GeneratorParams = detail.gen();
+            second.set_origin(shared_origin.unwrap_or_else(|| rng.gen()));
+            let generator = pick(&mut rng);
+            let second_generator = pick(&mut rng);
+            let weight = rng.gen_range(0.0..=1.0);
+            second.retain(&[second_generator]);
+            let second = Some((second_generator, Arc::new(second), weight));
+            (generator, roll(&generator, &mut rng), second)
+        } else {
+            let generator = pick(&mut rng);
+            (generator, roll(&generator, &mut rng), None)
+        };
+        //Flip a coin. If it lands heads-up, create another layer for use as a mask.
+        let mask = if game::maybe_with(&mut rng) {
+            let generator = pick(&mut rng);
+            Some((generator, roll(&generator, &mut rng)))
+        } else {
+            None
+        };
+        //Only keep the settings of the generators this layer actually draws with.
+        let mut used = vec![image.0];
+        used.extend(mask.map(|(generator, _)| generator));
+        params.retain(&used);
+        let params = Arc::new(params);
+        let (generator, roll, second) = image;
+        let image_source = TextureSource {
+            generator,
+            params: params.clone(),
+            mixed: second,
+            roll,
+        };
+        let mask_source = mask.map(|(generator, roll)| TextureSource {
+            generator,
+            params,
+            mixed: None,
+            roll,
+        });
+        Ok(ColourLayer {
+            image: Vec::new(),
+            mask: None,
+            //Flip another coin. If it lands heads-up, set the flag so we invert this layer.
+            invert_mask: game::maybe_with(&mut rng),
+            back,
+            fore,
+            image_source,
+            mask_source,
+        })
+    }
+    fn draw(&mut self, size: types::Area) {
+        //Draw the textures from their sources, at this size.
+        self.image = self.image_source.generate(size);
+        self.mask = self
+            .mask_source
+            .as_ref()
+            .map(|source| source.generate(size));
+    }
+    fn resized(&self, size: types::Area) -> Self {
+        //The same layer, with its textures drawn again at another size.
+        let mut layer = self.undrawn();
+        layer.draw(size);
+        layer
+    }
+    #[cfg(feature = "entropy")]
+    fn row(&self, size: types::Area, y: usize) -> Self {
+        //The same layer with only row y of its textures at this size, which is its row 0.
+        ColourLayer {
+            image: vec![self.image_source.generate_row(size, y)],
+            mask: self
+                .mask_source
+                .as_ref()
+                .map(|source| vec![source.generate_row(size, y)]),
+            ..self.undrawn()
+        }
+    }
+    fn undrawn(&self) -> Self {
+        ColourLayer {
+            image: Vec::new(),
+            mask: None,
+            invert_mask: self.invert_mask,
+            back: self.back,
+            fore: self.fore,
+            image_source: self.image_source.clone(),
+            mask_source: self.mask_source.clone(),
+        }
+    }
+}
+
+//What the layers of a new fish are made of, besides their colours.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct LayerOptions {
+    //Centre every layer on this point instead of scattering them.
+    shared_origin: Option<generators::GeneratorPoint>,
+    //Blend two generators into the image of every layer instead of using one.
+    mixed: bool,
+    //Only pick generators from these; never empty.
+    generators: Option<Vec<generators::Generators>>,
+}
+
+//Where the colours of the layers of a new fish come from.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum LayerColours {
+    Palette(ColourPalette),
+    /*
+    Drifting from the first palette at the front layer to the second at the
+    back. Only random_blended makes these, so a build without the entropy
+    feature never does; but it can still read them in with a fish.
+    */
+    #[cfg_attr(not(feature = "entropy"), allow(dead_code))]
+    Blended(ColourPalette, ColourPalette),
+}
+impl LayerColours {
+    fn sample<R: Rng + ?Sized>(&self, depth: f64, rng: &mut R) -> Result<Colour, String> {
+        //A colour for a layer, given how far down the stack it is.
+        match self {
+            LayerColours::Palette(palette) => palette.sample_with(rng),
+            LayerColours::Blended(from, to) => {
+                Ok(from.sample_with(rng)?.lerp(&to.sample_with(rng)?, depth))
             }
         }
-        Ok(outval)
     }
 }
 
-#[wasm_bindgen]
-pub fn new_fish_image() -> Box<[u8]> {
-    let width = 256;
-    let height = 256;
-    let fish = Jelatofish::random(
-        types::Area::new(width, height),
-        &Default::default(),
-        None,
-        None,
-    )
-    .unwrap();
-    const MAX_CHANVAL: f64 = 255.0;
+//Everything it takes to grow a seeded fish again.
+#[derive(Debug, Clone)]
+struct Recipe {
+    structure_seed: u64,
+    detail_seed: Option<u64>,
+    size: types::Area,
+    colours: ColourPalette,
+    layer_count: Option<usize>,
+    cutoff_threshold: Option<types::PixelVal>,
+    shared_origin: Option<generators::GeneratorPoint>,
+}
+impl Recipe {
+    const MAGIC: &'static [u8] = b"JF\x01";
 
-    let image: Vec<u8> = vec![vec![0 as f64; width]; height]
+    fn grow(&self) -> Result<Jelatofish, String> {
+        Jelatofish::random_seeded_with(
+            self.structure_seed,
+            self.detail_seed,
+            self.size,
+            &self.colours,
+            self.layer_count,
+            self.cutoff_threshold,
+            LayerOptions {
+                shared_origin: self.shared_origin,
+                ..Default::default()
+            },
+        )
+    }
+    fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        /*
+        The layout is little-endian throughout:
+            "JF", format version 1
+            one byte of flags saying which optional fields follow:
+                1 detail seed, 2 layer count, 4 cutoff threshold, 8 shared origin
+            structure seed (u64), then the detail seed (u64) if flagged
+            width and height (u32 each)
+            layer count (u8), cutoff threshold (f64), shared origin x and y
+            (f64 each), for whichever are flagged
+            number of palette colours (u16), then red, green, blue and alpha
+            (f64 each) for every colour
+        A palette too big to count in a u16 can't be written down at all.
+        */
+        let colour_count: u16 = self
+            .colours
+            .colours
+            .len()
+            .try_into()
+            .map_err(|_| format!("must have at most {} palette colours", u16::MAX))?;
+        let flags = [
+            self.detail_seed.is_some(),
+            self.layer_count.is_some(),
+            self.cutoff_threshold.is_some(),
+            self.shared_origin.is_some(),
+        ]
         .iter()
         .enumerate()
-        .flat_map(|(y, line)| {
-            let line: Vec<u8> = line
-                .iter()
-                .enumerate()
-                .flat_map(|(x, _)| {
-                    let p = fish
-                        .get_pixel_val(x, y)
-                        .unwrap()
-                        .scale(MAX_CHANVAL);
-                    vec![p.red as u8, p.green as u8, p.blue as u8, 255]
-                })
-                .collect();
-            line
+        .fold(0_u8, |flags, (bit, set)| flags | (u8::from(*set) << bit));
+        let mut bytes = Recipe::MAGIC.to_vec();
+        bytes.push(flags);
+        bytes.extend_from_slice(&self.structure_seed.to_le_bytes());
+        if let Some(seed) = self.detail_seed {
+            bytes.extend_from_slice(&seed.to_le_bytes());
+        }
+        bytes.extend_from_slice(&(self.size.width as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.size.height as u32).to_le_bytes());
+        if let Some(count) = self.layer_count {
+            bytes.push(count as u8);
+        }
+        if let Some(cutoff) = self.cutoff_threshold {
+            bytes.extend_from_slice(&cutoff.to_le_bytes());
+        }
+        if let Some(origin) = self.shared_origin {
+            bytes.extend_from_slice(&origin.x().to_le_bytes());
+            bytes.extend_from_slice(&origin.y().to_le_bytes());
+        }
+        bytes.extend_from_slice(&colour_count.to_le_bytes());
+        for c in &self.colours.colours {
+            for channel in &[c.red, c.green, c.blue, c.alpha] {
+                bytes.extend_from_slice(&channel.to_le_bytes());
+            }
+        }
+        Ok(bytes)
+    }
+    fn from_bytes(bytes: &[u8]) -> Result<Recipe, String> {
+        let mut bytes = RecipeReader { rest: bytes };
+        if bytes.take(Recipe::MAGIC.len())? != Recipe::MAGIC {
+            return Err("not a version 1 recipe".to_string());
+        }
+        let flags = bytes.take(1)?[0];
+        let structure_seed = bytes.u64()?;
+        let detail_seed = if flags & 1 != 0 {
+            Some(bytes.u64()?)
+        } else {
+            None
+        };
+        let size = types::Area::new(bytes.u32()? as usize, bytes.u32()? as usize);
+        let layer_count = if flags & 2 != 0 {
+            Some(bytes.take(1)?[0] as usize)
+        } else {
+            None
+        };
+        let cutoff_threshold = if flags & 4 != 0 {
+            Some(bytes.f64()?)
+        } else {
+            None
+        };
+        let shared_origin = if flags & 8 != 0 {
+            Some(generators::GeneratorPoint::new(bytes.f64()?, bytes.f64()?))
+        } else {
+            None
+        };
+        let count = u16::from_le_bytes(bytes.take(2)?.try_into().unwrap());
+        let colours = (0..count)
+            .map(|_| {
+                Ok(Colour::new(
+                    bytes.f64()?,
+                    bytes.f64()?,
+                    bytes.f64()?,
+                    bytes.f64()?,
+                ))
+            })
+            .collect::<Result<_, String>>()?;
+        if !bytes.rest.is_empty() {
+            return Err("recipe has bytes left over".to_string());
+        }
+        Ok(Recipe {
+            structure_seed,
+            detail_seed,
+            size,
+            colours: ColourPalette { colours },
+            layer_count,
+            cutoff_threshold,
+            shared_origin,
         })
-        .collect();
-    image.into_boxed_slice()
+    }
 }
 
-pub fn save_test_image(
-    width: usize,
-    height: usize,
-    generator: generators::Generators,
-    filename: &str,
-) {
-    let image = generators::generate(types::Area::new(width, height), &generator, &rand::random());
-    let mut imgbuf = image::ImageBuffer::new(width as u32, height as u32);
-
-    const MAX_CHANVAL: f64 = 255.0;
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        let p = image[x as usize][y as usize];
-        *pixel = image::Rgb([
-            (p * MAX_CHANVAL) as u8,
-            (p * MAX_CHANVAL) as u8,
-            (p * MAX_CHANVAL) as u8,
-        ]);
+struct RecipeReader<'a> {
+    rest: &'a [u8],
+}
+impl<'a> RecipeReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.rest.len() < count {
+            return Err("recipe is cut short".to_string());
+        }
+        let (head, tail) = self.rest.split_at(count);
+        self.rest = tail;
+        Ok(head)
+    }
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
     }
-    imgbuf.save(&Path::new(filename)).unwrap();
 }
 
-pub fn save_fish_image(width: usize, height: usize, filename: &str) {
-    let fish = Jelatofish::random(
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "JelatofishData"))]
+pub struct Jelatofish {
+    size: types::Area,
+    cutoff_threshold: types::PixelVal,
+    layers: Vec<ColourLayer>,
+    //What the layers were grown from, for reroll_layer.
+    colours: LayerColours,
+    options: LayerOptions,
+    //How to grow this fish again, if it came from a seed and hasn't been changed since.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    recipe: Option<Recipe>,
+}
+
+//A fish as it is read back in, before its textures are drawn again.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct JelatofishData {
+    size: types::Area,
+    cutoff_threshold: types::PixelVal,
+    layers: Vec<LayerData>,
+    colours: LayerColours,
+    options: LayerOptions,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct LayerData {
+    fore: Colour,
+    back: Colour,
+    invert_mask: bool,
+    image_source: TextureSource,
+    mask_source: Option<TextureSource>,
+}
+
+#[cfg(feature = "serde")]
+impl std::convert::TryFrom<JelatofishData> for Jelatofish {
+    type Error = String;
+
+    fn try_from(data: JelatofishData) -> Result<Self, Self::Error> {
+        /*
+        Hold a fish read in to the same limits as a new one before drawing
+        any of its textures, so a hand-edited size is an error rather than
+        an allocation that aborts the whole program.
+        */
+        let size = data.size;
+        Jelatofish::check_memory(size, data.layers.len(), Jelatofish::MAX_TEXTURE_BYTES)?;
+        if !(Jelatofish::MIN_LAYERS..=Jelatofish::MAX_LAYERS).contains(&data.layers.len()) {
+            return Err(format!(
+                "must be {} <= layer_count <= {}",
+                Jelatofish::MIN_LAYERS,
+                Jelatofish::MAX_LAYERS,
+            ));
+        }
+        if data.cutoff_threshold.is_nan()
+            || data.cutoff_threshold > Jelatofish::MAX_CUTOFF_THRESHOLD
+        {
+            return Err(format!(
+                "must be cutoff_threshold <= {}",
+                Jelatofish::MAX_CUTOFF_THRESHOLD
+            ));
+        }
+        Ok(Jelatofish {
+            size,
+            cutoff_threshold: data.cutoff_threshold,
+            layers: data
+                .layers
+                .into_iter()
+                .map(|layer| ColourLayer {
+                    image: layer.image_source.generate(size),
+                    mask: layer
+                        .mask_source
+                        .as_ref()
+                        .map(|source| source.generate(size)),
+                    fore: layer.fore,
+                    back: layer.back,
+                    invert_mask: layer.invert_mask,
+                    image_source: layer.image_source,
+                    mask_source: layer.mask_source,
+                })
+                .collect(),
+            colours: data.colours,
+            options: data.options,
+            recipe: None,
+        })
+    }
+}
+impl Jelatofish {
+    const MAX_LAYERS: usize = 6;
+    const MIN_LAYERS: usize = 2;
+
+    const MAX_CUTOFF_THRESHOLD: f64 = 1.0 / 16.0;
+
+    const BLANK_SAMPLES: usize = 8;
+    const BLANK_ALPHA: f64 = 1.0 / 256.0;
+
+    //The most memory the textures of one fish may take; see estimate_memory.
+    pub const MAX_TEXTURE_BYTES: usize = u32::MAX as usize;
+
+    #[cfg(feature = "entropy")]
+    pub fn random(
+        size: types::Area,
+        colours: &ColourPalette,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+    ) -> Result<Self, String> {
+        Jelatofish::random_with_colours(
+            size,
+            LayerColours::Palette(colours.clone()),
+            layer_count,
+            cutoff_threshold,
+            Default::default(),
+            None,
+        )
+    }
+    #[cfg(feature = "entropy")]
+    pub fn random_cancellable(
+        size: types::Area,
+        colours: &ColourPalette,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+        cancel: &AtomicBool,
+    ) -> Result<Self, String> {
+        /*
+        Same as random, but the flag is checked before each layer is grown.
+        Once it is set we stop at the next layer and return an error, so a
+        caller on another thread can give up on a big fish part way through.
+        */
+        Jelatofish::random_with_colours(
+            size,
+            LayerColours::Palette(colours.clone()),
+            layer_count,
+            cutoff_threshold,
+            Default::default(),
+            Some(cancel),
+        )
+    }
+    #[cfg(feature = "entropy")]
+    pub fn random_mixed(
+        size: types::Area,
+        colours: &ColourPalette,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+    ) -> Result<Self, String> {
+        /*
+        Like random, but the image of every layer is a blend of two
+        generators instead of one, mixed in a random proportion.
+        */
+        Jelatofish::random_with_colours(
+            size,
+            LayerColours::Palette(colours.clone()),
+            layer_count,
+            cutoff_threshold,
+            LayerOptions {
+                mixed: true,
+                ..Default::default()
+            },
+            None,
+        )
+    }
+    #[cfg(feature = "entropy")]
+    pub fn random_blended(
+        size: types::Area,
+        from: &ColourPalette,
+        to: &ColourPalette,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+    ) -> Result<Self, String> {
+        /*
+        Like random, but the colours drift from one palette to the other.
+        The front layer takes its colours from the first palette, the back
+        layer from the second, and the layers in between mix a colour from
+        each in proportion to how deep they sit in the stack.
+        */
+        Jelatofish::random_with_colours(
+            size,
+            LayerColours::Blended(from.clone(), to.clone()),
+            layer_count,
+            cutoff_threshold,
+            Default::default(),
+            None,
+        )
+    }
+    pub fn random_seeded(
+        seed: u64,
+        size: types::Area,
+        colours: &ColourPalette,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+    ) -> Result<Self, String> {
+        //Same as random, but the same seed always grows the same fish.
+        Jelatofish::random_seeded_with(
+            seed,
+            None,
+            size,
+            colours,
+            layer_count,
+            cutoff_threshold,
+            Default::default(),
+        )
+    }
+    pub fn random_with_seed(
+        size: types::Area,
+        colours: &ColourPalette,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+        seed: u64,
+    ) -> Result<Self, String> {
+        /*
+        Same as random_seeded, with the seed last.
+        Every random decision, down to each generator's settings and the roll
+        of each texture, is drawn from the one seed, so the same seed and
+        settings always give byte-identical images.
+        */
+        Jelatofish::random_seeded(seed, size, colours, layer_count, cutoff_threshold)
+    }
+    pub fn random_with_seeds(
+        structure_seed: u64,
+        detail_seed: u64,
+        size: types::Area,
+        colours: &ColourPalette,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+    ) -> Result<Self, String> {
+        /*
+        Same as random_seeded, but with the fine details of each generator
+        drawn from a seed of their own. Keep the structure seed and change
+        the detail seed to get the same layers, generators, colours and
+        placement with different textures.
+        */
+        Jelatofish::random_seeded_with(
+            structure_seed,
+            Some(detail_seed),
+            size,
+            colours,
+            layer_count,
+            cutoff_threshold,
+            Default::default(),
+        )
+    }
+    fn random_seeded_with(
+        structure_seed: u64,
+        detail_seed: Option<u64>,
+        size: types::Area,
+        colours: &ColourPalette,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+        options: LayerOptions,
+    ) -> Result<Self, String> {
+        let (has_recipe, shared_origin) = (options.generators.is_none(), options.shared_origin);
+        let grow = || {
+            Jelatofish::random_with_colours(
+                size,
+                LayerColours::Palette(colours.clone()),
+                layer_count,
+                cutoff_threshold,
+                options,
+                None,
+            )
+        };
+        let mut fish = match detail_seed {
+            Some(detail_seed) => game::with_seeds(structure_seed, detail_seed, grow),
+            None => game::with_seed(structure_seed, grow),
+        }?;
+        /*
+        A recipe can't say which generators were allowed, so only a fish
+        that could pick any of them remembers how to grow itself again.
+        */
+        if has_recipe {
+            fish.recipe = Some(Recipe {
+                structure_seed,
+                detail_seed,
+                size,
+                colours: colours.clone(),
+                layer_count,
+                cutoff_threshold,
+                shared_origin,
+            });
+        }
+        Ok(fish)
+    }
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        /*
+        Pack up how to grow this fish again - its seeds, size, palette and
+        settings, not its pixels - into a short blob of bytes that from_bytes
+        turns back into the same fish. A blob is a few dozen bytes plus 32
+        per palette colour, small enough to base64 into a URL.
+        Only fish grown from a seed can be packed up, and rerolling a layer
+        loses the recipe, since no seed grows that fish any more.
+        */
+        match &self.recipe {
+            Some(recipe) => recipe.to_bytes(),
+            None => Err("only an unchanged seeded fish has a recipe".to_string()),
+        }
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        Recipe::from_bytes(bytes)?.grow()
+    }
+    pub fn image_from_seed(width: usize, height: usize, seed: u64) -> Result<Vec<u8>, String> {
+        //Grow a fish from the seed with random colours and hand back its RGBA bytes.
+        Ok(Jelatofish::random_seeded(
+            seed,
+            types::Area::new(width, height),
+            &Default::default(),
+            None,
+            None,
+        )?
+        .render())
+    }
+    fn random_with_colours(
+        size: types::Area,
+        colours: LayerColours,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+        options: LayerOptions,
+        cancel: Option<&AtomicBool>,
+    ) -> Result<Self, String> {
+        //Pick the layers, then draw all of their textures, checking for a cancel before each one.
+        let mut fish =
+            Jelatofish::random_undrawn(size, colours, layer_count, cutoff_threshold, options)?;
+        Jelatofish::check_memory(size, fish.layers.len(), Jelatofish::MAX_TEXTURE_BYTES)?;
+        for layer in &mut fish.layers {
+            if cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+                return Err("cancelled".to_string());
+            }
+            layer.draw(size);
+        }
+        Ok(fish)
+    }
+    fn random_undrawn(
+        size: types::Area,
+        colours: LayerColours,
+        layer_count: Option<usize>,
+        cutoff_threshold: Option<types::PixelVal>,
+        options: LayerOptions,
+    ) -> Result<Self, String> {
+        /*
+        Create a series of layers which we will later use to generate
+        pixel data. These will contain the complete package of settings
+        used to calculate image values, but not the textures themselves,
+        which are left for draw to fill in.
+        The colours and options are kept with the fish, so that a layer
+        grown again later fits in with the rest.
+        */
+        let mut rng = game::get_rng();
+        let layer_count = match layer_count {
+            Some(x) if (Jelatofish::MIN_LAYERS..=Jelatofish::MAX_LAYERS).contains(&x) => x,
+            None => rng.gen_range(Jelatofish::MIN_LAYERS as u64..=Jelatofish::MAX_LAYERS as u64)
+                as usize,
+            _ => {
+                return Err(format!(
+                    "must be {} <= layer_count <= {}",
+                    Jelatofish::MIN_LAYERS,
+                    Jelatofish::MAX_LAYERS,
+                ))
+            }
+        };
+        let cutoff_threshold = match cutoff_threshold {
+            Some(x) if x <= Jelatofish::MAX_CUTOFF_THRESHOLD => x,
+            None => rng.gen_range(0.0..=Jelatofish::MAX_CUTOFF_THRESHOLD),
+            _ => {
+                return Err(format!(
+                    "must be cutoff_threshold <= {}",
+                    Jelatofish::MAX_CUTOFF_THRESHOLD
+                ))
+            }
+        };
+        let layers = (0..layer_count)
+            .map(|i| {
+                let depth = Jelatofish::layer_depth(i, layer_count);
+                ColourLayer::random(size, &colours, depth, &options)
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Jelatofish {
+            size,
+            cutoff_threshold,
+            layers,
+            colours,
+            options,
+            recipe: None,
+        })
+    }
+    fn layer_depth(index: usize, layer_count: usize) -> f64 {
+        //How far down the stack a layer is: 0.0 for the front layer, 1.0 for the back one.
+        index as f64 / usize::max(1, layer_count.saturating_sub(1)) as f64
+    }
+    pub fn estimate_memory(size: types::Area, layer_count: usize, mask_probability: f64) -> usize {
+        /*
+        Work out roughly how many bytes the textures of a fish this size will
+        take, so callers can check against a budget before growing one.
+        Every layer has an image, and a separate mask with the given
+        probability (random gives each layer an even chance of one).
+        A PixelMap is a Vec of rows, so on top of the pixels themselves each
+        row and the map as a whole cost one Vec header.
+        The numbers saturate rather than overflow for absurd sizes.
+        */
+        let header = std::mem::size_of::<Vec<f64>>();
+        let row = size
+            .width
+            .saturating_mul(std::mem::size_of::<f64>())
+            .saturating_add(header);
+        let map = size.height.saturating_mul(row).saturating_add(header);
+        let maps_per_layer = 1.0 + mask_probability.clamp(0.0, 1.0);
+        (map as f64 * maps_per_layer * layer_count as f64) as usize
+    }
+    fn check_memory(size: types::Area, layer_count: usize, max_bytes: usize) -> Result<(), String> {
+        /*
+        Refuse sizes whose textures would not fit in max_bytes, counting a
+        mask for every layer, so an absurd size is an error instead of an
+        allocation that aborts the whole program.
+        */
+        if size.width.checked_mul(size.height).is_none() {
+            return Err("must be width * height <= usize::MAX".to_string());
+        }
+        let bytes = Jelatofish::estimate_memory(size, layer_count, 1.0);
+        if bytes > max_bytes {
+            return Err(format!(
+                "must take at most {} bytes of textures, not {}",
+                max_bytes, bytes
+            ));
+        }
+        Ok(())
+    }
+    pub fn layer_images(&self) -> Vec<Vec<u8>> {
+        /*
+        Render every layer on its own, front layer first, as straight RGBA
+        bytes row by row. Each layer's mask becomes its alpha.
+        */
+        self.layers
+            .iter()
+            .map(|layer| {
+                (0..self.size.height)
+                    .flat_map(|y| (0..self.size.width).map(move |x| (x, y)))
+                    .flat_map(|(x, y)| {
+                        let p = layer.pixel(x, y);
+                        let [r, g, b] = p.to_rgb8();
+                        vec![r, g, b, types::to_chanval(p.alpha)]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+    pub fn mask_image(&self, layer: usize) -> Option<Vec<u8>> {
+        /*
+        Render the mask of one layer as one grey byte per pixel, row by row:
+        how much that layer covers each pixel, with the layer's invert_mask
+        already applied. A layer without a mask of its own uses its image as
+        the mask, so that is what comes out for it. There is only nothing to
+        show when the layer doesn't exist.
+        */
+        let layer = self.layers.get(layer)?;
+        Some(
+            (0..self.size.height)
+                .flat_map(|y| (0..self.size.width).map(move |x| (x, y)))
+                .map(|(x, y)| types::to_chanval(layer.pixel(x, y).alpha))
+                .collect(),
+        )
+    }
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, String> {
+        /*
+        Write the whole fish out: its size, its cutoff, and for every layer
+        its colours and what its textures are drawn from. The textures
+        themselves are left out and drawn again by from_json.
+        */
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        //Read back a fish from to_json; it renders exactly the same pixels.
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+    pub fn resize(&self, new_size: types::Area) -> Result<Jelatofish, String> {
+        /*
+        Draw the same fish again at another size. Every layer keeps its
+        generators, their settings, its colours and where its textures were
+        rolled to, so only the resolution changes. The roll is kept as a
+        share of the size, which rounds to the nearest pixel at the new one.
+        The new size has to fit in memory just like a new fish's.
+        */
+        Jelatofish::check_memory(new_size, self.layers.len(), Jelatofish::MAX_TEXTURE_BYTES)?;
+        Ok(Jelatofish {
+            size: new_size,
+            cutoff_threshold: self.cutoff_threshold,
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| layer.resized(new_size))
+                .collect(),
+            colours: self.colours.clone(),
+            options: self.options.clone(),
+            recipe: None,
+        })
+    }
+    pub fn reroll_layer<R: Rng + ?Sized>(
+        &mut self,
+        index: usize,
+        rng: &mut R,
+    ) -> Result<(), String> {
+        /*
+        Throw away one layer and grow a brand new one in its place: new
+        textures, new mask, new colours. All the other layers stay as they are.
+        The new layer is grown the way the old one was, from the same
+        palettes at the same depth, mixed and centred the same way, so it
+        still fits in with the rest. Every choice is drawn from the rng, so
+        the same rng state grows the same layer.
+        */
+        if index >= self.layers.len() {
+            return Err(format!("must be index < {}", self.layers.len()));
+        }
+        let depth = Jelatofish::layer_depth(index, self.layers.len());
+        let mut layer = game::with_seed(rng.gen(), || {
+            ColourLayer::random(self.size, &self.colours, depth, &self.options)
+        })?;
+        layer.draw(self.size);
+        self.layers[index] = layer;
+        self.recipe = None;
+        Ok(())
+    }
+    pub fn render_all(&self) -> Vec<Colour> {
+        /*
+        Every merged pixel, row by row, the same as calling get_pixel_val on
+        each one. The textures are walked in the order they are stored, and
+        since every pixel is known to be in range none of them are checked.
+        */
+        let depth = self.layers.len();
+        (0..self.size.height)
+            .flat_map(|y| (0..self.size.width).map(move |x| self.merge_pixel(x, y, depth)))
+            .collect()
+    }
+    pub fn get_pixel_val(&self, x: usize, y: usize) -> Result<Colour, String> {
+        self.get_pixel_val_of(x, y, self.layers.len())
+    }
+    fn get_pixel_val_of(&self, x: usize, y: usize, depth: usize) -> Result<Colour, String> {
+        /*
+        Calculate one pixel from the front depth layers only; get_pixel_val
+        uses all of them.
+        We start with a black pixel.
+        Then we loop through all of the layers, calculating each one with its
+        mask. We then merge each layer's resulting pixel onto the out image.
+        Once we're done, we return the merged pixel.
+        We use alpha kind of backwards: high values mean high opacity, low values
+        mean low opacity.
+        */
+        //Did we get valid parameters?
+        if x >= self.size.width || y >= self.size.height {
+            return Err(format!(
+                "must be x < {} && y < {}",
+                self.size.width, self.size.height
+            ));
+        }
+        Ok(self.merge_pixel(x, y, depth))
+    }
+    fn merge_pixel(&self, x: usize, y: usize, depth: usize) -> Colour {
+        /*
+        The merged pixel is kept as four loose channels rather than a Colour,
+        and each layer's gradient value is folded straight into them, so the
+        inner loop doesn't build any intermediate structs.
+        */
+        let (mut red, mut green, mut blue, mut alpha) = (0.0, 0.0, 0.0, 0.0);
+        for layer in self.layers.iter().take(depth) {
+            //Get the image value for this pixel, for this layer.
+            let imageval = layer.image[y][x];
+            //Do we have a mask texture? If we do, calculate its value.
+            let maskval = match &layer.mask {
+                Some(mask) => mask[y][x],
+                None => imageval,
+            };
+            //Are we supposed to invert the mask value we got?
+            let maskval = if layer.invert_mask {
+                1.0 - maskval
+            } else {
+                maskval
+            };
+            /*
+            Now we are ready. Calculate the image value for this layer.
+            We use the image value as the proportion of the distance between
+            two colours. We calculate this one channel at a time. This results
+            in a smooth gradient of colour from min to max.
+            The image value for this layer is calculated.
+            But the image is more than just this layer: it is the merged
+            results of all the layers. So now we merge this value with
+            the existing value calculated from the previous layers.
+            We use the alpha channel to determine the proportion of blending.
+            The new layer goes behind the existing layers; we use the existing
+            alpha channel to determine what proportion of the new value shows
+            through.
+            */
+            let (fore, back) = (&layer.fore, &layer.back);
+            let through = 1.0 - alpha;
+            red = (red * alpha) + ((imageval * (fore.red - back.red) + back.red) * through);
+            green =
+                (green * alpha) + ((imageval * (fore.green - back.green) + back.green) * through);
+            blue = (blue * alpha) + ((imageval * (fore.blue - back.blue) + back.blue) * through);
+            /*
+            Add the alpha channels (representing opacity); if the result is greater
+            than 100% opacity, we just stop calculating (since no further layers
+            will produce visible data).
+            */
+            let layeralpha = maskval * through;
+            if layeralpha + alpha + self.cutoff_threshold >= 1.0 {
+                alpha = 1.0;
+                /*
+                And now end the loop, because we've collected all the data we need.
+                Calculating pixels from any of the deeper layers would just be a waste of time.
+                */
+                break;
+            } else {
+                alpha += layeralpha;
+            }
+        }
+        Colour::new(red, green, blue, alpha)
+    }
+    pub fn mean_luminance(&self, alpha_weighted: bool) -> f64 {
+        /*
+        Average the luminance of every pixel in the composite image.
+        If alpha_weighted is set, each pixel counts in proportion to its
+        composite alpha, so faint areas barely move the result.
+        A fully transparent image has no luminance to speak of; we call it 0.
+        */
+        let mut total = 0.0;
+        let mut total_weight = 0.0;
+        for y in 0..self.size.height {
+            for x in 0..self.size.width {
+                let p = self.get_pixel_val(x, y).unwrap();
+                let weight = if alpha_weighted { p.alpha } else { 1.0 };
+                total += p.luminance() * weight;
+                total_weight += weight;
+            }
+        }
+        if total_weight > 0.0 {
+            total / total_weight
+        } else {
+            0.0
+        }
+    }
+    pub fn mean_hsv(&self) -> (f64, f64, f64) {
+        /*
+        Average the hue, saturation and value of every pixel in the composite
+        image, with all three from 0 to 1 as in Colour::to_hsv.
+        Hue goes round in a circle, so we can't just add it up: half red at
+        0.0 and half red at 0.99 would come out cyan. Instead each hue is a
+        step round the colour wheel, and we see where the steps lead. Steps
+        are as long as the pixel's saturation, so greys, whose hue means
+        nothing, don't pull the result either way. If the hues cancel out
+        altogether we call the hue 0.
+        */
+        let turn = 2.0 * std::f64::consts::PI;
+        let (mut across, mut up, mut saturation, mut value) = (0.0, 0.0, 0.0, 0.0);
+        for y in 0..self.size.height {
+            for x in 0..self.size.width {
+                let (h, s, v) = self.get_pixel_val(x, y).unwrap().to_hsv();
+                across += (h * turn).cos() * s;
+                up += (h * turn).sin() * s;
+                saturation += s;
+                value += v;
+            }
+        }
+        let count = (self.size.width * self.size.height) as f64;
+        if count == 0.0 {
+            return (0.0, 0.0, 0.0);
+        }
+        let hue = if across == 0.0 && up == 0.0 {
+            0.0
+        } else {
+            up.atan2(across).rem_euclid(turn) / turn
+        };
+        (hue, saturation / count, value / count)
+    }
+    pub fn find_seed(
+        size: types::Area,
+        colours: &ColourPalette,
+        target_luminance: f64,
+        tolerance: f64,
+        max_tries: usize,
+    ) -> Option<u64> {
+        /*
+        Look for a seed whose fish comes out about as bright as we want.
+        This is a plain brute-force search: we grow the fish for seeds 0, 1,
+        2 and so on, and hand back the first seed whose mean luminance is
+        within the tolerance of the target. Each try grows a whole fish at
+        this size, so the search gets slow quickly for big images.
+        If no seed is close enough within max_tries, we give up.
+        */
+        (0..max_tries as u64).find(|&seed| {
+            Jelatofish::random_seeded(seed, size, colours, None, None).is_ok_and(|fish| {
+                (fish.mean_luminance(false) - target_luminance).abs() <= tolerance
+            })
+        })
+    }
+    pub fn is_blank(&self) -> bool {
+        /*
+        Check whether the fish came out with nothing to show.
+        If every layer's mask happens to sit near zero, no layer shows through
+        and the whole image is transparent. Rather than render every pixel, we
+        look at an evenly spaced grid of them and call the fish blank if none
+        of them has enough alpha to change even one step of an 8-bit channel.
+        */
+        let samples = |length: usize| {
+            let count = usize::min(length, Jelatofish::BLANK_SAMPLES);
+            (0..count).map(move |i| i * length / count)
+        };
+        samples(self.size.height).all(|y| {
+            samples(self.size.width)
+                .all(|x| self.get_pixel_val(x, y).unwrap().alpha < Jelatofish::BLANK_ALPHA)
+        })
+    }
+    pub fn phash(&self) -> u64 {
+        /*
+        Boil the image down to a 64-bit fingerprint that barely changes when
+        the image barely changes, for spotting near-duplicate fish.
+        This is the average hash: we shrink the image to 8 x 8 with
+        render_preview, work out each cell's luminance, and set one bit per
+        cell, row by row from the most significant bit, wherever the cell is
+        brighter than the average of all 64. Fish that look alike differ in
+        only a few bits, so compare hashes by the number of bits that differ
+        ((a ^ b).count_ones()) rather than for equality.
+        An empty image has nothing to fingerprint and hashes to 0.
+        */
+        if self.size.width == 0 || self.size.height == 0 {
+            return 0;
+        }
+        let preview = self.render_preview(8, 8, 4).unwrap();
+        let cells: Vec<f64> = preview
+            .chunks(4)
+            .map(|p| {
+                Colour::new(p[0] as f64, p[1] as f64, p[2] as f64, 1.0)
+                    .scale(1.0 / 255.0)
+                    .luminance()
+            })
+            .collect();
+        let mean = cells.iter().sum::<f64>() / cells.len() as f64;
+        cells
+            .iter()
+            .fold(0, |hash, &cell| (hash << 1) | (cell > mean) as u64)
+    }
+    pub fn to_svg(&self, bands: usize, width: usize, height: usize) -> Result<String, String> {
+        /*
+        Build a coarse vector version of the fish.
+        The image is sampled on a grid of width x height cells, and each cell
+        is sorted into one of a number of luminance bands. Every band is
+        painted with the average colour of the cells that fell into it.
+        Neighbouring cells in a row that share a band are merged into one
+        rectangle to keep the document small. One grid cell is one unit in
+        the viewBox, so the result scales to whatever size it is shown at.
+        */
+        if bands == 0 {
+            return Err("must be bands >= 1".to_string());
+        }
+        if width == 0 || height == 0 {
+            return Err("must be width >= 1 && height >= 1".to_string());
+        }
+        let cells = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| {
+                        self.get_pixel_val(
+                            (x * 2 + 1) * self.size.width / (width * 2),
+                            (y * 2 + 1) * self.size.height / (height * 2),
+                        )
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let band_of = |c: &Colour| usize::min(bands - 1, (c.luminance() * bands as f64) as usize);
+
+        //Average each band's colour so it stands in for all of its cells.
+        let mut sums = vec![(0.0, 0.0, 0.0, 0); bands];
+        for c in cells.iter().flatten() {
+            let sum = &mut sums[band_of(c)];
+            sum.0 += c.red;
+            sum.1 += c.green;
+            sum.2 += c.blue;
+            sum.3 += 1;
+        }
+        let fills: Vec<String> = sums
+            .iter()
+            .map(|&(red, green, blue, count)| {
+                let [r, g, b] = Colour::new(red, green, blue, 0.0)
+                    .scale(1.0 / count.max(1) as f64)
+                    .to_rgb8();
+                format!("#{:02x}{:02x}{:02x}", r, g, b)
+            })
+            .collect();
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\" \
+             width=\"{}\" height=\"{}\" shape-rendering=\"crispEdges\">\n",
+            width, height, self.size.width, self.size.height
+        );
+        for (y, row) in cells.iter().enumerate() {
+            let mut x = 0;
+            while x < width {
+                let band = band_of(&row[x]);
+                let run = row[x..].iter().take_while(|c| band_of(c) == band).count();
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"1\" fill=\"{}\"/>\n",
+                    x, y, run, fills[band]
+                ));
+                x += run;
+            }
+        }
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+    pub fn render(&self) -> Vec<u8> {
+        self.render_ordered(ChannelOrder::Rgba)
+    }
+    pub fn render_to_rgba8(&self, buf: &mut [u8]) -> Result<(), String> {
+        /*
+        Write every pixel from get_pixel_val into the caller's own buffer, row
+        by row as RGBA bytes, so one allocation can be reused for every frame.
+        The buffer has to be exactly width * height * 4 bytes long.
+        Unlike render, the alpha is the composite alpha, so a canvas can show
+        through wherever the layers thin out.
+        */
+        let len = self.size.width * self.size.height * 4;
+        if buf.len() != len {
+            return Err(format!("must be buf.len() == {}", len));
+        }
+        for (i, pixel) in buf.chunks_exact_mut(4).enumerate() {
+            let (x, y) = (i % self.size.width, i / self.size.width);
+            let p = self.get_pixel_val(x, y)?;
+            let [r, g, b] = p.to_rgb8();
+            pixel.copy_from_slice(&[r, g, b, types::to_chanval(p.alpha)]);
+        }
+        Ok(())
+    }
+    pub fn render_ordered(&self, channel_order: ChannelOrder) -> Vec<u8> {
+        self.render_graded(channel_order, None)
+    }
+    pub fn render_graded(
+        &self,
+        channel_order: ChannelOrder,
+        gamma: Option<[types::PixelVal; 3]>,
+    ) -> Vec<u8> {
+        /*
+        Calculate every pixel of the image, row by row, as four bytes per pixel
+        in the requested channel order.
+        If a red, green and blue gamma are given, each pixel is graded with
+        them on the way out; see Colour::apply_channel_gamma.
+        The composite alpha only says how much of the layers we found; the
+        image itself is always opaque.
+        */
+        (0..self.size.height)
+            .flat_map(|y| (0..self.size.width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let p = self.get_pixel_val(x, y).unwrap();
+                let p = match gamma {
+                    Some([red, green, blue]) => p.apply_channel_gamma(red, green, blue),
+                    None => p,
+                };
+                let [r, g, b] = p.to_rgb8();
+                match channel_order {
+                    ChannelOrder::Rgba => vec![r, g, b, 255],
+                    ChannelOrder::Bgra => vec![b, g, r, 255],
+                }
+            })
+            .collect()
+    }
+    pub fn render_with<F: Fn(&[LayerSample]) -> Colour>(&self, f: F) -> Vec<u8> {
+        /*
+        Render the image with a compositing function of the caller's own in
+        place of get_pixel_val. For every pixel, f gets what each layer has
+        there, front layer first, and returns the finished colour, which
+        comes out as opaque RGBA bytes like render. The textures are the same
+        ones the fish always draws; only the way they are merged changes.
+        */
+        let mut samples = Vec::with_capacity(self.layers.len());
+        let mut pixels = Vec::with_capacity(self.size.width * self.size.height * 4);
+        for y in 0..self.size.height {
+            for x in 0..self.size.width {
+                samples.clear();
+                samples.extend(self.layers.iter().map(|layer| layer.sample(x, y)));
+                let [r, g, b] = f(&samples).to_rgb8();
+                pixels.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        pixels
+    }
+    pub fn build_up_frames(&self) -> Vec<Vec<u8>> {
+        /*
+        Render the image as it builds up, one frame per layer, as opaque RGBA
+        bytes like render. Frame 0 has only the front layer, each frame after
+        it adds the next layer behind, and the last frame is the finished
+        image. Played in order they show the fish being assembled.
+        */
+        (1..=self.layers.len())
+            .map(|depth| {
+                (0..self.size.height)
+                    .flat_map(|y| (0..self.size.width).map(move |x| (x, y)))
+                    .flat_map(|(x, y)| {
+                        let [r, g, b] = self.get_pixel_val_of(x, y, depth).unwrap().to_rgb8();
+                        vec![r, g, b, 255]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+    pub fn render_with_stats(&self) -> (Vec<u8>, texture::TextureStats) {
+        /*
+        Same as render, but the luminance of every composite pixel is also
+        gathered into running statistics on the way past, so callers that want
+        both don't go over the image twice.
+        */
+        let mut stats = texture::TextureStats::new();
+        let mut pixels = Vec::with_capacity(self.size.width * self.size.height * 4);
+        for y in 0..self.size.height {
+            for x in 0..self.size.width {
+                let p = self.get_pixel_val(x, y).unwrap();
+                stats.push(p.luminance());
+                let [r, g, b] = p.to_rgb8();
+                pixels.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        (pixels, stats)
+    }
+
+    pub fn render_preview(
+        &self,
+        width: usize,
+        height: usize,
+        block: usize,
+    ) -> Result<Vec<u8>, String> {
+        /*
+        Render a smaller copy of the image, width x height, as opaque RGBA
+        bytes like render.
+        Each preview pixel covers a patch of the full image. Rather than
+        take one pixel from it, which turns busy textures into noise, we
+        average a block x block grid of pixels spread evenly across the
+        patch. A block of 1 takes just the patch's top left pixel; bigger
+        blocks are smoother but cost block * block pixels each.
+        */
+        if block == 0 {
+            return Err("must be block >= 1".to_string());
+        }
+        if width == 0 || height == 0 {
+            return Err("must be width >= 1 and height >= 1".to_string());
+        }
+        let spread = |i: usize, step: usize, preview: usize, full: usize| {
+            (i * full * block + step * full) / (preview * block)
+        };
+        let samples = (block * block) as f64;
+        let mut out = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Colour::default();
+                for j in 0..block {
+                    for i in 0..block {
+                        let p = self.get_pixel_val(
+                            spread(x, i, width, self.size.width),
+                            spread(y, j, height, self.size.height),
+                        )?;
+                        sum = Colour::new(
+                            sum.red + p.red,
+                            sum.green + p.green,
+                            sum.blue + p.blue,
+                            sum.alpha + p.alpha,
+                        );
+                    }
+                }
+                let [r, g, b] = sum.scale(1.0 / samples).to_rgb8();
+                out.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+        Ok(out)
+    }
+    pub fn rows(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        /*
+        Calculate the image one row at a time, top to bottom, as three bytes
+        (red, green, blue) per pixel. Only the row being handed out is ever
+        held, so callers can stream an image of any height.
+        */
+        (0..self.size.height).map(move |y| self.row_bytes(y))
+    }
+    fn row_bytes(&self, y: usize) -> Vec<u8> {
+        (0..self.size.width)
+            .flat_map(|x| self.get_pixel_val(x, y).unwrap().to_rgb8().to_vec())
+            .collect()
+    }
+    #[cfg(feature = "entropy")]
+    fn undrawn_rows(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        /*
+        Same as rows, for a fish whose textures were never drawn: each row
+        of every layer is drawn from its sources just before it is merged.
+        */
+        (0..self.size.height).map(move |y| {
+            let row = Jelatofish {
+                size: types::Area::new(self.size.width, 1),
+                cutoff_threshold: self.cutoff_threshold,
+                layers: self
+                    .layers
+                    .iter()
+                    .map(|layer| layer.row(self.size, y))
+                    .collect(),
+                colours: self.colours.clone(),
+                options: self.options.clone(),
+                recipe: None,
+            };
+            row.row_bytes(0)
+        })
+    }
+    pub fn render_standard_alpha(&self) -> Vec<u8> {
+        /*
+        Calculate every pixel of the image, row by row, as straight
+        (non-premultiplied) RGBA bytes, keeping the composite alpha instead of
+        flattening it to opaque.
+        Inside get_pixel_val the alpha is the coverage collected from the
+        layers, 0.0 where no layer showed through and 1.0 where the layers
+        together reached full opacity. The colour channels are always a
+        weighted mix whose weights sum to one, so they are already straight
+        colour and are never multiplied by the alpha. The conversion is
+        therefore, per pixel:
+            R = round(255 * clamp(red, 0, 1))
+            G = round(255 * clamp(green, 0, 1))
+            B = round(255 * clamp(blue, 0, 1))
+            A = round(255 * clamp(alpha, 0, 1))
+        Compositing the result over a background with the ordinary "over"
+        operator, out = colour * A + background * (1 - A), gives the image
+        as the layers saw it over that background.
+        */
+        (0..self.size.height)
+            .flat_map(|y| (0..self.size.width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let colour = self.get_pixel_val(x, y).unwrap();
+                let [r, g, b] = colour.to_rgb8();
+                vec![r, g, b, types::to_chanval(colour.alpha)]
+            })
+            .collect()
+    }
+}
+
+/*
+Collects the settings for a new fish one at a time, so each one is named at
+the call site instead of being one of a row of Options. Anything left unset
+is picked at random, just as Jelatofish::random does with None.
+*/
+#[derive(Debug, Default, Clone)]
+pub struct JelatofishBuilder {
+    size: types::Area,
+    palette: ColourPalette,
+    layer_count: Option<usize>,
+    cutoff_threshold: Option<types::PixelVal>,
+    max_bytes: Option<usize>,
+    seed: Option<u64>,
+    allowed_generators: Option<Vec<generators::Generators>>,
+    shared_origin: Option<generators::GeneratorPoint>,
+}
+impl JelatofishBuilder {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn size(mut self, size: types::Area) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn palette(mut self, palette: &ColourPalette) -> Self {
+        self.palette = palette.clone();
+        self
+    }
+    pub fn layers(mut self, layer_count: usize) -> Self {
+        self.layer_count = Some(layer_count);
+        self
+    }
+    pub fn cutoff_threshold(mut self, cutoff_threshold: types::PixelVal) -> Self {
+        self.cutoff_threshold = Some(cutoff_threshold);
+        self
+    }
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        /*
+        A tighter limit on the memory the textures may take than
+        Jelatofish::MAX_TEXTURE_BYTES, which always applies as well.
+        */
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+    pub fn seed(mut self, seed: u64) -> Self {
+        //Grow the fish from this seed, so the same settings always give the same fish.
+        self.seed = Some(seed);
+        self
+    }
+    pub fn allowed_generators(mut self, generators: &[generators::Generators]) -> Self {
+        //Only draw the layers with these generators, picked evenly among them.
+        self.allowed_generators = Some(generators.to_vec());
+        self
+    }
+    pub fn shared_origin(mut self, origin: generators::GeneratorPoint) -> Self {
+        /*
+        Centre every layer on this point instead of scattering them, so the
+        radial generators line up into one concentric design.
+        */
+        self.shared_origin = Some(origin);
+        self
+    }
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        /*
+        Check every setting and report everything that is wrong with them at
+        once, rather than stopping at the first problem the way growing the
+        fish does, so a caller can fix them all in one go.
+        */
+        let mut problems = Vec::new();
+        if self.size.width == 0 || self.size.height == 0 {
+            problems.push("must be width >= 1 && height >= 1".to_string());
+        }
+        if let Some(layer_count) = self.layer_count {
+            if !(Jelatofish::MIN_LAYERS..=Jelatofish::MAX_LAYERS).contains(&layer_count) {
+                problems.push(format!(
+                    "must be {} <= layer_count <= {}",
+                    Jelatofish::MIN_LAYERS,
+                    Jelatofish::MAX_LAYERS,
+                ));
+            }
+        }
+        if let Some(cutoff_threshold) = self.cutoff_threshold {
+            if cutoff_threshold.is_nan() || cutoff_threshold > Jelatofish::MAX_CUTOFF_THRESHOLD {
+                problems.push(format!(
+                    "must be cutoff_threshold <= {}",
+                    Jelatofish::MAX_CUTOFF_THRESHOLD
+                ));
+            }
+        }
+        //Without a layer count yet, allow for as many layers as there could be.
+        let max_bytes = self
+            .max_bytes
+            .map_or(Jelatofish::MAX_TEXTURE_BYTES, |max_bytes| {
+                max_bytes.min(Jelatofish::MAX_TEXTURE_BYTES)
+            });
+        let layer_count = self.layer_count.unwrap_or(Jelatofish::MAX_LAYERS);
+        if let Err(problem) = Jelatofish::check_memory(self.size, layer_count, max_bytes) {
+            problems.push(problem);
+        }
+        if self
+            .allowed_generators
+            .as_ref()
+            .is_some_and(|generators| generators.is_empty())
+        {
+            problems.push("must allow at least one generator".to_string());
+        }
+        //Off the tile, the fish could be grown but never read back in.
+        if let Some(origin) = self.shared_origin {
+            if !(0.0..=1.0).contains(&origin.x()) || !(0.0..=1.0).contains(&origin.y()) {
+                problems.push("must be 0.0 <= shared_origin x/y <= 1.0".to_string());
+            }
+        }
+        #[cfg(not(feature = "entropy"))]
+        if self.seed.is_none() {
+            problems.push("must give a seed, since this build has no entropy feature".to_string());
+        }
+        for (i, colour) in self.palette.colours.iter().enumerate() {
+            if !colour.is_in_range() {
+                problems.push(format!(
+                    "palette colour {} must be 0.0 <= r/g/b/a <= 1.0",
+                    i
+                ));
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+    pub fn build(&self) -> Result<Jelatofish, String> {
+        self.validate().map_err(|problems| problems.join("; "))?;
+        let options = LayerOptions {
+            shared_origin: self.shared_origin,
+            generators: self.allowed_generators.clone(),
+            ..Default::default()
+        };
+        match self.seed {
+            Some(seed) => Jelatofish::random_seeded_with(
+                seed,
+                None,
+                self.size,
+                &self.palette,
+                self.layer_count,
+                self.cutoff_threshold,
+                options,
+            ),
+            None => Jelatofish::random_with_colours(
+                self.size,
+                LayerColours::Palette(self.palette.clone()),
+                self.layer_count,
+                self.cutoff_threshold,
+                options,
+                None,
+            ),
+        }
+    }
+}
+
+//The order of the four bytes making up each pixel of a rendered image.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOrder {
+    Rgba,
+    Bgra,
+}
+
+#[cfg(feature = "entropy")]
+#[wasm_bindgen]
+pub fn new_fish_image() -> Box<[u8]> {
+    new_fish_image_ordered(ChannelOrder::Rgba)
+}
+
+#[cfg(feature = "entropy")]
+#[wasm_bindgen]
+pub fn new_fish_image_ordered(channel_order: ChannelOrder) -> Box<[u8]> {
+    /*
+    The fish is grown from a random seed rather than straight from random,
+    so that current_params can hand out a link that grows it again.
+    */
+    fish_image_from_seed(channel_order, game::random())
+}
+
+#[cfg(not(feature = "entropy"))]
+#[wasm_bindgen]
+pub fn new_fish_image(seed: u64) -> Box<[u8]> {
+    new_fish_image_ordered(ChannelOrder::Rgba, seed)
+}
+
+#[cfg(not(feature = "entropy"))]
+#[wasm_bindgen]
+pub fn new_fish_image_ordered(channel_order: ChannelOrder, seed: u64) -> Box<[u8]> {
+    //Built without the entropy feature there is no random seed to be had, so the page brings one.
+    fish_image_from_seed(channel_order, seed)
+}
+
+fn fish_image_from_seed(channel_order: ChannelOrder, seed: u64) -> Box<[u8]> {
+    let width = 256;
+    let height = 256;
+    let fish = Jelatofish::random_seeded(
+        seed,
+        types::Area::new(width, height),
+        &Default::default(),
+        None,
+        None,
+    )
+    .unwrap();
+    remember_recipe(&fish);
+    fish.render_ordered(channel_order).into_boxed_slice()
+}
+
+#[wasm_bindgen]
+pub fn new_fish_image_with_palette(
+    width: usize,
+    height: usize,
+    seed: u64,
+    hex_colours: Vec<String>,
+) -> Result<Box<[u8]>, String> {
+    /*
+    Grow a fish from the seed in colours of the caller's choosing, given
+    as CSS hex strings (see ColourPalette::from_hex), and hand back its
+    RGBA bytes. An empty list means random colours.
+    */
+    let fish = Jelatofish::random_seeded(
+        seed,
+        types::Area::new(width, height),
+        &ColourPalette::from_hex(&hex_colours)?,
+        None,
+        None,
+    )?;
+    remember_recipe(&fish);
+    Ok(fish.render().into_boxed_slice())
+}
+
+thread_local! {
+    //The recipe of the last fish the wasm exports grew, for current_params.
+    static LAST_RECIPE: std::cell::RefCell<Option<Vec<u8>>> = const { std::cell::RefCell::new(None) };
+}
+
+fn remember_recipe(fish: &Jelatofish) {
+    LAST_RECIPE.with(|last| *last.borrow_mut() = fish.to_bytes().ok());
+}
+
+#[wasm_bindgen]
+pub fn fish_from_params(blob: &str) -> Result<Box<[u8]>, String> {
+    /*
+    Grow a fish from a shareable link and hand back its RGBA bytes.
+    The blob is either a recipe from current_params, or a seed and size
+    like "seed=1234&w=512&h=512" (the size defaults to 256 by 256). A
+    leading "#" is skipped, so the hash of a URL can be passed straight in.
+    */
+    let blob = blob.trim_start_matches('#');
+    let fish = if blob.contains('=') {
+        let (mut seed, mut width, mut height) = (None, 256, 256);
+        for pair in blob.split('&') {
+            let mut parts = pair.splitn(2, '=');
+            let (key, value) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+            let number = |value: &str| value.parse::<u64>().map_err(|e| format!("{}: {}", key, e));
+            let length = |value: &str| match usize::try_from(number(value)?) {
+                Ok(0) => Err(format!("must be {} >= 1", key)),
+                Ok(length) => Ok(length),
+                Err(e) => Err(format!("{}: {}", key, e)),
+            };
+            match key {
+                "seed" => seed = Some(number(value)?),
+                "w" => width = length(value)?,
+                "h" => height = length(value)?,
+                _ => return Err(format!("unknown parameter {}", key)),
+            }
+        }
+        Jelatofish::random_seeded(
+            seed.ok_or_else(|| "must give a seed".to_string())?,
+            types::Area::new(width, height),
+            &Default::default(),
+            None,
+            None,
+        )?
+    } else {
+        let bytes =
+            base64::decode_config(blob, base64::URL_SAFE_NO_PAD).map_err(|e| e.to_string())?;
+        Jelatofish::from_bytes(&bytes)?
+    };
+    remember_recipe(&fish);
+    Ok(fish.render().into_boxed_slice())
+}
+
+#[wasm_bindgen]
+pub fn current_params() -> Option<String> {
+    /*
+    A shareable blob for the last fish grown through new_fish_image or
+    fish_from_params: its recipe in URL-safe base64, ready to go after the
+    "#" of a link and back into fish_from_params.
+    */
+    LAST_RECIPE.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map(|bytes| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD))
+    })
+}
+
+pub fn contact_sheet(
+    size: types::Area,
+    seeds: &[u64],
+    cols: usize,
+    colours: &ColourPalette,
+) -> Result<Vec<u8>, String> {
+    /*
+    Grow one fish per seed at the given size and lay them out in a grid,
+    left to right and then top to bottom, as one big RGBA image.
+    The sheet is cols thumbnails wide and as many rows tall as it takes;
+    any cells left over at the end of the last row stay transparent.
+    Each fish is grown at twice the size and shrunk with render_preview, so
+    a busy texture is averaged down rather than turned into noise.
+    */
+    const BLOCK: usize = 2;
+    if cols == 0 {
+        return Err("must be cols >= 1".to_string());
+    }
+    let grown = match (
+        size.width.checked_mul(BLOCK),
+        size.height.checked_mul(BLOCK),
+    ) {
+        (Some(width), Some(height)) => types::Area::new(width, height),
+        _ => return Err("must be width * 2 <= usize::MAX && height * 2 <= usize::MAX".to_string()),
+    };
+    let rows = seeds.len().div_ceil(cols);
+    let stride = cols * size.width * 4;
+    let mut sheet = vec![0_u8; stride * rows * size.height];
+    for (i, seed) in seeds.iter().enumerate() {
+        let fish = Jelatofish::random_seeded(*seed, grown, colours, None, None)?;
+        let thumbnail = fish.render_preview(size.width, size.height, BLOCK)?;
+        let (left, top) = ((i % cols) * size.width * 4, (i / cols) * size.height);
+        for (y, line) in thumbnail.chunks(size.width * 4).enumerate() {
+            let start = (top + y) * stride + left;
+            sheet[start..start + line.len()].copy_from_slice(line);
+        }
+    }
+    Ok(sheet)
+}
+
+#[cfg(feature = "entropy")]
+pub fn gradient_map(
+    size: types::Area,
+    generator: &generators::Generators,
+    params: &generators::GeneratorParams,
+    ramp: &[(f64, Colour)],
+) -> Vec<u8> {
+    /*
+    Generate one texture and colour it through a ramp of colour stops, as
+    RGBA bytes row by row. Each stop pins a colour to a texture value; values
+    between two stops mix their colours in proportion, and values beyond the
+    first or last stop take that stop's colour. The stops may come in any
+    order. An empty ramp paints everything black.
+    Like render, the image is opaque whatever the stops' alpha.
+    */
+    gradient_map_with_rng(size, generator, params, ramp, &mut game::get_rng())
+}
+
+pub fn gradient_map_with_rng<R: Rng + ?Sized>(
+    size: types::Area,
+    generator: &generators::Generators,
+    params: &generators::GeneratorParams,
+    ramp: &[(f64, Colour)],
+    rng: &mut R,
+) -> Vec<u8> {
+    //Same as gradient_map, but the texture is rolled by the given rng.
+    let mut stops = ramp.to_vec();
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let colour_at = |value: f64| match stops.iter().position(|stop| stop.0 >= value) {
+        None => stops.last().map_or_else(Colour::default, |stop| stop.1),
+        Some(0) => stops[0].1,
+        //Right on a stop; this also keeps two stops at the same value apart.
+        Some(i) if stops[i].0 == value => stops[i].1,
+        Some(i) => {
+            let ((low, from), (high, to)) = (stops[i - 1], stops[i]);
+            from.lerp(&to, (value - low) / (high - low))
+        }
+    };
+    generators::generate(size, generator, params, rng)
+        .iter()
+        .flatten()
+        .flat_map(|&value| {
+            let [r, g, b] = colour_at(value).to_rgb8();
+            vec![r, g, b, 255]
+        })
+        .collect()
+}
+
+#[cfg(feature = "entropy")]
+pub fn save_test_image(
+    width: usize,
+    height: usize,
+    generator: generators::Generators,
+    filename: &str,
+) {
+    let image = generators::generate(
+        types::Area::new(width, height),
+        &generator,
+        &game::random(),
+        &mut game::get_rng(),
+    );
+    let mut imgbuf = image::ImageBuffer::new(width as u32, height as u32);
+
+    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+        let p = types::to_chanval(image[y as usize][x as usize]);
+        *pixel = image::Rgb([p, p, p]);
+    }
+    imgbuf.save(&Path::new(filename)).unwrap();
+}
+
+#[cfg(feature = "entropy")]
+pub fn save_fish_image(width: usize, height: usize, filename: &str) {
+    let fish = Jelatofish::random(
         types::Area::new(width, height),
         &Default::default(),
         None,
@@ -351,13 +2257,1351 @@ pub fn save_fish_image(width: usize, height: usize, filename: &str) {
     .unwrap();
     let mut imgbuf = image::ImageBuffer::new(width as u32, height as u32);
 
-    const MAX_CHANVAL: f64 = 255.0;
     for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        let p = fish
-            .get_pixel_val(x as usize, y as usize)
-            .unwrap()
-            .scale(MAX_CHANVAL);
-        *pixel = image::Rgb([p.red as u8, p.green as u8, p.blue as u8]);
+        *pixel = image::Rgb(
+            fish.get_pixel_val(x as usize, y as usize)
+                .unwrap()
+                .to_rgb8(),
+        );
     }
     imgbuf.save(&Path::new(filename)).unwrap();
 }
+
+#[cfg(feature = "entropy")]
+pub fn save_fish_png_streaming(width: usize, height: usize, filename: &str) -> Result<(), String> {
+    /*
+    Same as save_fish_image, but nothing is ever drawn for the whole image:
+    the layers keep only the sources of their textures, and each row of
+    the PNG is drawn from them, merged and written out before the next one.
+    So the size is only limited by the time it takes, not by memory.
+    */
+    let fish = Jelatofish::random_undrawn(
+        types::Area::new(width, height),
+        LayerColours::Palette(Default::default()),
+        None,
+        None,
+        Default::default(),
+    )?;
+    let file = std::fs::File::create(Path::new(filename)).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    let mut stream = writer.stream_writer();
+    for row in fish.undrawn_rows() {
+        stream.write_all(&row).map_err(|e| e.to_string())?;
+    }
+    stream.finish().map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "entropy")]
+pub fn save_fish_indexed(
+    width: usize,
+    height: usize,
+    filename: &str,
+    max_colours: usize,
+) -> Result<(), String> {
+    /*
+    Grow a fish, cut its colours down to at most max_colours with median
+    cut, and write it out as an indexed PNG. Every pixel takes the palette
+    colour nearest to its own.
+    */
+    if !(1..=256).contains(&max_colours) {
+        return Err("must be 1 <= max_colours <= 256".to_string());
+    }
+    let fish = Jelatofish::random(
+        types::Area::new(width, height),
+        &Default::default(),
+        None,
+        None,
+    )?;
+    let pixels: Vec<[u8; 3]> = fish
+        .rows()
+        .flat_map(|row| {
+            row.chunks(3)
+                .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let palette = quantize::median_cut(&pixels, max_colours);
+    //Neighbouring pixels often share a colour, so remember where each one landed.
+    let mut found = std::collections::HashMap::new();
+    let indices: Vec<u8> = pixels
+        .iter()
+        .map(|&pixel| {
+            *found
+                .entry(pixel)
+                .or_insert_with(|| quantize::nearest(&palette, pixel).unwrap_or(0) as u8)
+        })
+        .collect();
+    let file = std::fs::File::create(Path::new(filename)).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette.concat());
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(&indices).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "entropy")]
+pub fn save_fish_png_compact(width: usize, height: usize, filename: &str) -> Result<(), String> {
+    /*
+    Grow a fish and write it out as a PNG squeezed as small as we can get
+    it without losing anything; see encode_png_compact. It takes several
+    times as long to write as save_fish_image.
+    */
+    let fish = Jelatofish::random(
+        types::Area::new(width, height),
+        &Default::default(),
+        None,
+        None,
+    )?;
+    let data = encode_png_compact(width, height, &fish.render())?;
+    std::fs::write(Path::new(filename), data).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "entropy")]
+fn encode_png_compact(width: usize, height: usize, rgba: &[u8]) -> Result<Vec<u8>, String> {
+    /*
+    Encode the image as small as a plain PNG gets, choosing the settings to
+    suit this particular image.
+    A fish rendered opaque carries an alpha channel of nothing but 255, so
+    when that is all there is we leave it out and save a quarter of the
+    data outright. Then, which filter predicts the pixels best depends on the
+    picture: smooth gradients suit Paeth or Up, flat bands suit Sub. Rather
+    than guess, we compress with each filter at the best compression and
+    keep whichever comes out smallest.
+    */
+    let (color, pixels) = if rgba.chunks(4).all(|pixel| pixel[3] == 255) {
+        let rgb: Vec<u8> = rgba
+            .chunks(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect();
+        (png::ColorType::RGB, rgb)
+    } else {
+        (png::ColorType::RGBA, rgba.to_vec())
+    };
+    let encode = |filter: png::FilterType| -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, width as u32, height as u32);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::Best);
+        encoder.set_filter(filter);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer
+            .write_image_data(&pixels)
+            .map_err(|e| e.to_string())?;
+        drop(writer);
+        Ok(data)
+    };
+    let filters = [
+        png::FilterType::NoFilter,
+        png::FilterType::Sub,
+        png::FilterType::Up,
+        png::FilterType::Avg,
+        png::FilterType::Paeth,
+    ];
+    Ok(filters
+        .iter()
+        .map(|filter| encode(*filter))
+        .collect::<Result<Vec<_>, String>>()?
+        .into_iter()
+        .min_by_key(Vec::len)
+        .unwrap())
+}
+
+#[cfg(feature = "entropy")]
+fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(rgba).map_err(|e| e.to_string())?;
+    drop(writer);
+    Ok(data)
+}
+
+#[cfg(feature = "entropy")]
+pub fn save_fish_ora(width: usize, height: usize, filename: &str) -> Result<(), String> {
+    /*
+    Grow a fish and write it out as an OpenRaster file, so it can be taken
+    apart and worked on further in a painting program.
+    The file is a ZIP holding one PNG per layer, listed in stack.xml from
+    the front layer down, with each layer's mask as its alpha. The layers
+    are plain "normal" layers at full opacity. Our own merge is not quite
+    a normal stack (see get_pixel_val), so the stacked layers are a close,
+    editable approximation; the exact render is stored alongside as the
+    merged image and, shrunk down, as the thumbnail.
+    */
+    let fish = Jelatofish::random(
+        types::Area::new(width, height),
+        &Default::default(),
+        None,
+        None,
+    )?;
+    let merged = fish.render();
+    let thumbnail = image::RgbaImage::from_raw(width as u32, height as u32, merged.clone())
+        .ok_or_else(|| "image is too large".to_string())?;
+    let scale = f64::min(1.0, 256.0 / usize::max(width, height) as f64);
+    let thumbnail = image::imageops::thumbnail(
+        &thumbnail,
+        u32::max(1, (width as f64 * scale) as u32),
+        u32::max(1, (height as f64 * scale) as u32),
+    );
+
+    let mut stack = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <image version=\"0.0.5\" w=\"{}\" h=\"{}\">\n<stack>\n",
+        width, height
+    );
+    let mut files = Vec::new();
+    for (i, layer) in fish.layer_images().iter().enumerate() {
+        let src = format!("data/layer{}.png", i);
+        stack += &format!(
+            "<layer name=\"Layer {}\" src=\"{}\" x=\"0\" y=\"0\" opacity=\"1.0\" \
+             visibility=\"visible\" composite-op=\"svg:src-over\"/>\n",
+            i + 1,
+            src
+        );
+        files.push((src, encode_png(width, height, layer)?));
+    }
+    stack += "</stack>\n</image>\n";
+    files.push((
+        "mergedimage.png".to_string(),
+        encode_png(width, height, &merged)?,
+    ));
+    files.push((
+        "Thumbnails/thumbnail.png".to_string(),
+        encode_png(
+            thumbnail.width() as usize,
+            thumbnail.height() as usize,
+            thumbnail.as_raw(),
+        )?,
+    ));
+
+    let file = std::fs::File::create(Path::new(filename)).map_err(|e| e.to_string())?;
+    let mut zip = zip::ZipWriter::new(std::io::BufWriter::new(file));
+    //The mimetype has to come first, and uncompressed, so the file can be recognised.
+    let stored =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file("mimetype", stored)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(b"image/openraster")
+        .map_err(|e| e.to_string())?;
+    zip.start_file("stack.xml", Default::default())
+        .map_err(|e| e.to_string())?;
+    zip.write_all(stack.as_bytes()).map_err(|e| e.to_string())?;
+    //PNGs are compressed already.
+    for (name, data) in files {
+        zip.start_file(name, stored).map_err(|e| e.to_string())?;
+        zip.write_all(&data).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rng::SeedableRng;
+    use super::*;
+
+    fn seeded_fish(seed: u64, width: usize, height: usize) -> Jelatofish {
+        JelatofishBuilder::new()
+            .size(types::Area::new(width, height))
+            .seed(seed)
+            .build()
+            .unwrap()
+    }
+
+    fn channels(c: &Colour) -> [f64; 4] {
+        [c.red, c.green, c.blue, c.alpha]
+    }
+
+    fn two_colour_fish() -> Jelatofish {
+        //White on the left half, and black covering half of the right half.
+        let mut fish = seeded_fish(1, 4, 2);
+        let (white, black) = (
+            Colour::new(1.0, 1.0, 1.0, 1.0),
+            Colour::new(0.0, 0.0, 0.0, 1.0),
+        );
+        for (index, layer) in fish.layers.iter_mut().enumerate() {
+            let front = index == 0;
+            layer.image = vec![vec![1.0, 1.0, 0.0, 0.0]; 2];
+            layer.mask = Some(vec![
+                if front {
+                    vec![1.0, 1.0, 0.5, 0.5]
+                } else {
+                    vec![0.0; 4]
+                };
+                2
+            ]);
+            layer.invert_mask = false;
+            layer.fore = if front { white } else { black };
+            layer.back = black;
+        }
+        fish
+    }
+
+    fn merge_with_colours(fish: &Jelatofish, x: usize, y: usize) -> Colour {
+        let samples: Vec<_> = fish.layers.iter().map(|layer| layer.sample(x, y)).collect();
+        merge_samples(&samples, fish.cutoff_threshold)
+    }
+
+    fn merge_samples(samples: &[LayerSample], cutoff_threshold: types::PixelVal) -> Colour {
+        //The merge the way it was written before it worked on loose channels.
+        let mut outval: Colour = Default::default();
+        for sample in samples {
+            let (fore, back) = (sample.fore, sample.back);
+            let mut layerpixel = Colour {
+                alpha: sample.mask,
+                ..Colour::new(
+                    sample.image * (fore.red - back.red) + back.red,
+                    sample.image * (fore.green - back.green) + back.green,
+                    sample.image * (fore.blue - back.blue) + back.blue,
+                    0.0,
+                )
+            };
+            outval.red = (outval.red * outval.alpha) + (layerpixel.red * (1.0 - outval.alpha));
+            outval.green =
+                (outval.green * outval.alpha) + (layerpixel.green * (1.0 - outval.alpha));
+            outval.blue = (outval.blue * outval.alpha) + (layerpixel.blue * (1.0 - outval.alpha));
+            layerpixel.alpha *= 1.0 - outval.alpha;
+            if layerpixel.alpha + outval.alpha + cutoff_threshold >= 1.0 {
+                outval.alpha = 1.0;
+                break;
+            }
+            outval.alpha += layerpixel.alpha;
+        }
+        outval
+    }
+
+    #[test]
+    fn scalar_merge_matches_colour_merge() {
+        for seed in 0..4 {
+            let fish = seeded_fish(seed, 48, 32);
+            for y in 0..32 {
+                for x in 0..48 {
+                    assert_eq!(
+                        channels(&fish.get_pixel_val(x, y).unwrap()),
+                        channels(&merge_with_colours(&fish, x, y)),
+                        "seed {} pixel {}, {}",
+                        seed,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bad_palette_colour_is_an_error() {
+        let palette = ColourPalette {
+            colours: vec![Colour::new(2.0, 0.0, 0.0, 1.0)],
+        };
+        let fish = Jelatofish::random_seeded(0, types::Area::new(8, 8), &palette, None, None);
+        assert!(fish.is_err());
+    }
+
+    #[test]
+    fn fore_and_back_differ_when_the_palette_cannot_tell_them_apart() {
+        //Both colours are the same red, green and blue, so the back colour has to be nudged.
+        let palette = ColourPalette {
+            colours: vec![
+                Colour::new(0.25, 0.5, 1.0, 1.0),
+                Colour::new(0.25, 0.5, 1.0, 0.0),
+            ],
+        };
+        for seed in 0..4 {
+            let fish =
+                Jelatofish::random_seeded(seed, types::Area::new(8, 8), &palette, None, None)
+                    .unwrap();
+            for layer in &fish.layers {
+                assert!(!layer.fore.same_rgb(&layer.back));
+            }
+        }
+    }
+
+    #[test]
+    fn image_from_seed_is_reproducible() {
+        let image = |seed| Jelatofish::image_from_seed(16, 8, seed).unwrap();
+        assert_eq!(image(1), image(1));
+        assert_ne!(image(1), image(2));
+        assert!(Jelatofish::image_from_seed(usize::MAX, usize::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn rerolling_a_layer_changes_only_that_layer() {
+        let mut fish = seeded_fish(7, 32, 32);
+        let pixels = |fish: &Jelatofish| -> Vec<Vec<[f64; 4]>> {
+            fish.layers
+                .iter()
+                .map(|layer| {
+                    (0..32 * 32)
+                        .map(|i| channels(&layer.pixel(i % 32, i / 32)))
+                        .collect()
+                })
+                .collect()
+        };
+        let before = pixels(&fish);
+        fish.reroll_layer(0, &mut game::GameRng::seed_from_u64(1))
+            .unwrap();
+        let after = pixels(&fish);
+        assert_ne!(before[0], after[0]);
+        assert_eq!(before[1..], after[1..]);
+        assert!(fish
+            .reroll_layer(fish.layers.len(), &mut game::GameRng::seed_from_u64(1))
+            .is_err());
+    }
+    #[test]
+    #[cfg(feature = "entropy")]
+    fn undrawn_rows_match_the_drawn_fish() {
+        let size = types::Area::new(24, 16);
+        let grow = |seed| {
+            game::with_seed(seed, || {
+                Jelatofish::random_undrawn(
+                    size,
+                    LayerColours::Palette(Default::default()),
+                    None,
+                    None,
+                    Default::default(),
+                )
+            })
+            .unwrap()
+        };
+        let mut drawn = grow(5);
+        for layer in &mut drawn.layers {
+            layer.draw(size);
+        }
+        assert!(grow(5).undrawn_rows().eq(drawn.rows()));
+    }
+    #[test]
+    fn huge_palettes_have_no_recipe() {
+        let palette = |count| ColourPalette {
+            colours: (0..count)
+                .map(|i| Colour::new(i as f64 / count as f64, 0.5, 0.5, 1.0))
+                .collect(),
+        };
+        let grow = |count| {
+            JelatofishBuilder::new()
+                .size(types::Area::new(8, 8))
+                .palette(&palette(count))
+                .seed(3)
+                .build()
+                .unwrap()
+        };
+        let bytes = grow(3).to_bytes().unwrap();
+        assert_eq!(
+            Jelatofish::from_bytes(&bytes).unwrap().render(),
+            grow(3).render()
+        );
+        assert!(grow(u16::MAX as usize + 1).to_bytes().is_err());
+    }
+    #[test]
+    fn phash_tells_an_inverted_fish_apart() {
+        let hash = seeded_fish(11, 64, 64).phash();
+        assert_eq!(seeded_fish(11, 64, 64).phash(), hash);
+        //Inverting the colours of every layer inverts the whole image, and so its luminance.
+        let mut inverted = seeded_fish(11, 64, 64);
+        let invert = |c: Colour| Colour::new(1.0 - c.red, 1.0 - c.green, 1.0 - c.blue, c.alpha);
+        for layer in &mut inverted.layers {
+            layer.fore = invert(layer.fore);
+            layer.back = invert(layer.back);
+        }
+        assert!((inverted.phash() ^ hash).count_ones() > 48);
+    }
+    #[test]
+    fn inverted_masks_export_as_one_minus_the_mask() {
+        let mut fish = seeded_fish(13, 32, 16);
+        for index in 0..fish.layers.len() {
+            fish.layers[index].invert_mask = true;
+            let image = fish.mask_image(index).unwrap();
+            let layer = &fish.layers[index];
+            let raw = layer.mask.as_ref().unwrap_or(&layer.image);
+            let expected: Vec<u8> = raw
+                .iter()
+                .flatten()
+                .map(|value| types::to_chanval(1.0 - value))
+                .collect();
+            assert_eq!(image, expected);
+        }
+        assert_eq!(fish.mask_image(fish.layers.len()), None);
+    }
+    #[test]
+    fn validate_reports_every_problem() {
+        let builder = JelatofishBuilder::new()
+            .size(types::Area::new(0, 16))
+            .layers(Jelatofish::MAX_LAYERS + 1)
+            .cutoff_threshold(f64::NAN)
+            .allowed_generators(&[])
+            .seed(1)
+            .palette(&ColourPalette {
+                colours: vec![
+                    Colour::new(0.5, 0.5, 0.5, 1.0),
+                    Colour::new(2.0, 0.0, 0.0, 1.0),
+                ],
+            });
+        let problems = builder.validate().unwrap_err();
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        for part in &[
+            "width",
+            "layer_count",
+            "cutoff_threshold",
+            "generator",
+            "colour 1",
+        ] {
+            assert!(
+                problems.iter().any(|problem| problem.contains(part)),
+                "{}",
+                part
+            );
+        }
+        assert_eq!(builder.build().unwrap_err(), problems.join("; "));
+    }
+    #[test]
+    fn online_stats_match_a_full_pass() {
+        let fish = seeded_fish(17, 48, 32);
+        let (pixels, stats) = fish.render_with_stats();
+        assert_eq!(pixels, fish.render());
+        //Work the same figures out the slow way, from every luminance at once.
+        let values: Vec<f64> = fish.render_all().iter().map(Colour::luminance).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance =
+            values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64;
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        assert_eq!(stats.count(), values.len());
+        assert!((stats.mean() - mean).abs() < 1e-12);
+        assert!((stats.variance() - variance).abs() < 1e-12);
+        assert_eq!((stats.min(), stats.max()), (min, max));
+    }
+    #[test]
+    fn render_with_the_built_in_merge_is_render() {
+        let fish = seeded_fish(19, 40, 24);
+        let rendered = fish.render_with(|samples| merge_samples(samples, fish.cutoff_threshold));
+        assert_eq!(rendered, fish.render());
+    }
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        //A hash that is the same on every platform and every release, unlike the std one.
+        bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    #[test]
+    fn seeded_fish_are_pinned() {
+        /*
+        Written down once; the same pixels must come out of every build,
+        with or without the entropy feature. If a change means to alter what
+        seeds grow, pin the new hash here.
+        */
+        assert_eq!(
+            fnv1a(&seeded_fish(1, 64, 64).render()),
+            16651979441898656623
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "entropy"))]
+    fn unseeded_builders_are_refused() {
+        let builder = JelatofishBuilder::new().size(types::Area::new(16, 16));
+        assert!(builder.validate().unwrap_err()[0].contains("seed"));
+        assert!(builder.seed(1).validate().is_ok());
+    }
+    #[test]
+    fn resizing_keeps_the_design() {
+        let fish = seeded_fish(3, 32, 24);
+        let larger = fish.resize(types::Area::new(64, 48)).unwrap();
+        assert_eq!(larger.layers.len(), fish.layers.len());
+        for (resized, layer) in larger.layers.iter().zip(&fish.layers) {
+            assert_eq!(channels(&resized.fore), channels(&layer.fore));
+            assert_eq!(channels(&resized.back), channels(&layer.back));
+            assert_eq!(resized.image.len(), 48);
+        }
+        //Every roll lands on whole pixels at both sizes, so going back gives the same fish.
+        let back = larger.resize(types::Area::new(32, 24)).unwrap();
+        assert_eq!(back.render(), fish.render());
+    }
+
+    #[test]
+    fn tall_and_wide_fish_render() {
+        for &(width, height) in &[(64, 128), (128, 64)] {
+            let fish = seeded_fish(4, width, height);
+            assert_eq!(fish.render().len(), width * height * 4);
+            for layer in &fish.layers {
+                //Row first: one row per line of pixels, each as wide as the fish.
+                assert_eq!(layer.image.len(), height);
+                assert!(layer.image.iter().all(|row| row.len() == width));
+            }
+            assert!(fish.get_pixel_val(width - 1, height - 1).is_ok());
+        }
+    }
+
+    #[test]
+    fn absurd_sizes_are_an_error() {
+        //Both are refused before a single texture is allocated.
+        let build = |width, height| {
+            JelatofishBuilder::new()
+                .size(types::Area::new(width, height))
+                .seed(1)
+                .build()
+        };
+        assert!(build(usize::MAX, 2).unwrap_err().contains("usize::MAX"));
+        assert!(build(1 << 20, 1 << 20).unwrap_err().contains("bytes"));
+        assert!(seeded_fish(1, 8, 8)
+            .resize(types::Area::new(1 << 20, 1 << 20))
+            .is_err());
+    }
+
+    #[test]
+    fn pixels_past_either_edge_are_an_error() {
+        let fish = seeded_fish(1, 16, 8);
+        assert!(fish.get_pixel_val(15, 7).is_ok());
+        assert!(fish.get_pixel_val(16, 0).is_err());
+        assert!(fish.get_pixel_val(0, 8).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn fish_read_back_from_json_render_the_same() {
+        let fish = seeded_fish(2, 32, 24);
+        let json = fish.to_json().unwrap();
+        /*
+        No texture or fractal matrix is written out, only what they are drawn
+        from; one matrix alone would be 65536 numbers.
+        */
+        assert!(json.len() < 64 * 1024, "{} bytes", json.len());
+        assert_eq!(
+            Jelatofish::from_json(&json).unwrap().render(),
+            fish.render()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn fish_past_the_limits_are_not_read() {
+        let json: serde_json::Value =
+            serde_json::from_str(&seeded_fish(2, 8, 8).to_json().unwrap()).unwrap();
+        let read = |edit: &dyn Fn(&mut serde_json::Value)| {
+            let mut json = json.clone();
+            edit(&mut json);
+            Jelatofish::from_json(&json.to_string())
+        };
+        assert!(read(&|_| ()).is_ok());
+        assert!(read(&|json| json["size"]["width"] = (1_u64 << 40).into()).is_err());
+        assert!(read(&|json| json["cutoff_threshold"] = 0.5.into()).is_err());
+        assert!(read(&|json| json["layers"].as_array_mut().unwrap().truncate(1)).is_err());
+    }
+
+    #[test]
+    fn render_all_is_every_pixel_val() {
+        let fish = seeded_fish(6, 12, 10);
+        let all = fish.render_all();
+        assert_eq!(all.len(), 12 * 10);
+        for (i, colour) in all.iter().enumerate() {
+            let pixel = fish.get_pixel_val(i % 12, i / 12).unwrap();
+            assert_eq!(channels(colour), channels(&pixel), "pixel {}", i);
+        }
+    }
+
+    #[test]
+    fn hsv_corner_cases() {
+        let close = |a: Colour, b: Colour| {
+            let (a, b) = (channels(&a), channels(&b));
+            a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-12)
+        };
+        //Greys and black have no hue or saturation, and come back from any hue.
+        for &grey in &[0.0, 0.5, 1.0] {
+            assert_eq!(
+                Colour::new(grey, grey, grey, 1.0).to_hsv(),
+                (0.0, 0.0, grey)
+            );
+            assert!(close(
+                Colour::from_hsv(0.7, 0.0, grey, 1.0),
+                Colour::new(grey, grey, grey, 1.0)
+            ));
+        }
+        //Hues go round the wheel, from either side and a hair below a whole turn.
+        let red = Colour::new(1.0, 0.0, 0.0, 1.0);
+        for &hue in &[0.0, 1.0, -1.0, 2.0, -1e-20] {
+            assert!(
+                close(Colour::from_hsv(hue, 1.0, 1.0, 1.0), red),
+                "hue {}",
+                hue
+            );
+        }
+        assert!(close(
+            Colour::from_hsv(-1.0 / 3.0, 1.0, 1.0, 1.0),
+            Colour::new(0.0, 0.0, 1.0, 1.0)
+        ));
+        //Red with more blue than green sits just below a whole turn, not below zero.
+        assert_eq!(Colour::new(1.0, 0.0, 0.5, 1.0).to_hsv().0, 11.0 / 12.0);
+        //Every sector of the wheel comes back the way it went in, alpha and all.
+        for sector in 0..6 {
+            let hue = (sector as f64 + 0.25) / 6.0;
+            let colour = Colour::from_hsv(hue, 0.6, 0.8, 0.3);
+            let (back, saturation, value) = colour.to_hsv();
+            assert!(
+                (back - hue).abs() < 1e-12 && (saturation - 0.6).abs() < 1e-12,
+                "{}",
+                hue
+            );
+            assert_eq!((value, colour.alpha), (0.8, 0.3));
+        }
+    }
+
+    #[test]
+    fn two_colour_fish_have_a_known_mean_luminance() {
+        let fish = two_colour_fish();
+        assert!((fish.mean_luminance(false) - 0.5).abs() < 1e-9);
+        //The white half is fully covered and the black half only half, so white counts double.
+        assert!((fish.mean_luminance(true) - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn svgs_are_well_formed_with_a_fill_per_band() {
+        let fish = two_colour_fish();
+        let svg = fish.to_svg(2, 4, 2).unwrap();
+        //Every tag is closed, innermost first, and every attribute is quoted.
+        let mut open = vec![];
+        for tag in svg.split('<').skip(1) {
+            let tag = &tag[..tag.find('>').unwrap()];
+            assert_eq!(tag.matches('"').count() % 2, 0, "{}", tag);
+            match tag.strip_prefix('/') {
+                Some(name) => assert_eq!(open.pop(), Some(name)),
+                None if !tag.ends_with('/') => open.push(tag.split_whitespace().next().unwrap()),
+                None => (),
+            }
+        }
+        assert!(open.is_empty(), "{:?}", open);
+        let fills: std::collections::HashSet<_> = svg
+            .split("fill=\"")
+            .skip(1)
+            .map(|rest| &rest[..7])
+            .collect();
+        assert_eq!(fills, ["#ffffff", "#000000"].iter().copied().collect());
+        for &(bands, width, height) in &[(0, 4, 2), (2, 0, 2), (2, 4, 0)] {
+            assert!(fish.to_svg(bands, width, height).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "entropy")]
+    fn blended_fish_start_and_end_on_their_palettes() {
+        let reds = ColourPalette::from_hex(&["#f00", "#800000"]).unwrap();
+        let blues = ColourPalette::from_hex(&["#00f", "#000080"]).unwrap();
+        for _ in 0..4 {
+            let fish =
+                Jelatofish::random_blended(types::Area::new(8, 8), &reds, &blues, Some(4), None)
+                    .unwrap();
+            let (front, back) = (&fish.layers[0], &fish.layers[3]);
+            for colour in &[front.fore, front.back] {
+                assert!(colour.red > 0.0 && colour.green == 0.0 && colour.blue == 0.0);
+            }
+            for colour in &[back.fore, back.back] {
+                assert!(colour.red == 0.0 && colour.green == 0.0 && colour.blue > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn single_and_double_precision_colours_render_alike() {
+        fn shades<T: Float + SampleUniform>(palette: &ColourPalette<T>) -> Vec<[u8; 3]> {
+            let mut rng = game::GameRng::seed_from_u64(3);
+            (0..16)
+                .map(|i| {
+                    let (a, b) = (palette.sample_with(&mut rng), palette.sample_with(&mut rng));
+                    let mixed = a
+                        .unwrap()
+                        .lerp(&b.unwrap(), T::from(i as f64 / 16.0).unwrap());
+                    let (hue, saturation, value) = mixed.to_hsv();
+                    let colour = Colour::from_hsv(hue, saturation, value, T::one());
+                    colour.cast::<f64>().to_rgb8()
+                })
+                .collect()
+        }
+        let hex = ["#e0a040", "#2060c0", "#80ff10"];
+        let single = ColourPalette::<f32>::from_hex(&hex).unwrap();
+        let double = ColourPalette::<f64>::from_hex(&hex).unwrap();
+        //Single precision can land a hair to the other side of a rounding step.
+        for (a, b) in shades(&single).iter().zip(&shades(&double)) {
+            assert!(
+                a.iter().zip(b).all(|(a, b)| a.max(b) - a.min(b) <= 1),
+                "{:?} {:?}",
+                a,
+                b
+            );
+        }
+        let fish = |palette: &ColourPalette| {
+            let builder = JelatofishBuilder::new().size(types::Area::new(16, 16));
+            builder.palette(palette).seed(2).build().unwrap().render()
+        };
+        assert_eq!(fish(&single.cast()), fish(&double));
+    }
+
+    #[test]
+    fn contact_sheets_lay_previews_out_in_columns() {
+        let (size, seeds) = (types::Area::new(8, 6), [11, 12, 13, 14]);
+        let palette = ColourPalette::default();
+        let sheet = contact_sheet(size, &seeds, 2, &palette).unwrap();
+        //Two thumbnails across and two down.
+        let stride = 2 * size.width * 4;
+        assert_eq!(sheet.len(), stride * 2 * size.height);
+        let cell = |i: usize| -> Vec<u8> {
+            let (left, top) = ((i % 2) * size.width * 4, (i / 2) * size.height);
+            (top..top + size.height)
+                .flat_map(|y| sheet[y * stride + left..][..size.width * 4].to_vec())
+                .collect()
+        };
+        for (i, &seed) in seeds.iter().enumerate() {
+            let grown = types::Area::new(size.width * 2, size.height * 2);
+            let fish = Jelatofish::random_seeded(seed, grown, &palette, None, None).unwrap();
+            assert_eq!(
+                cell(i),
+                fish.render_preview(size.width, size.height, 2).unwrap()
+            );
+            assert!((0..i).all(|other| cell(other) != cell(i)), "cell {}", i);
+        }
+    }
+
+    #[test]
+    fn bgra_is_rgba_with_red_and_blue_swapped() {
+        let fish = seeded_fish(5, 16, 8);
+        let swapped: Vec<u8> = fish
+            .render()
+            .chunks(4)
+            .flat_map(|pixel| vec![pixel[2], pixel[1], pixel[0], pixel[3]])
+            .collect();
+        assert_eq!(fish.render_ordered(ChannelOrder::Bgra), swapped);
+    }
+
+    #[test]
+    fn doubled_white_is_clamped_to_white() {
+        let white = Colour::new(1.0, 1.0, 1.0, 1.0);
+        assert_eq!(channels(&white.scale(2.0)), [2.0; 4]);
+        assert_eq!(channels(&white.scale_clamped(2.0)), [1.0; 4]);
+        assert_eq!(white.scale(2.0).to_rgb8(), [255; 3]);
+    }
+
+    #[test]
+    fn detail_seeds_keep_the_structure() {
+        let grow = |detail_seed| {
+            let size = types::Area::new(24, 16);
+            Jelatofish::random_with_seeds(7, detail_seed, size, &Default::default(), None, None)
+                .unwrap()
+        };
+        let structure = |fish: &Jelatofish| -> Vec<_> {
+            let generator = |source: &TextureSource| {
+                (source.generator, source.mixed.as_ref().map(|mixed| mixed.0))
+            };
+            fish.layers
+                .iter()
+                .map(|layer| {
+                    let colours = (channels(&layer.fore), channels(&layer.back));
+                    let mask = layer.mask_source.as_ref().map(generator);
+                    (
+                        generator(&layer.image_source),
+                        mask,
+                        layer.invert_mask,
+                        colours,
+                    )
+                })
+                .collect()
+        };
+        let first = grow(0);
+        for detail_seed in 1..4 {
+            let fish = grow(detail_seed);
+            assert_eq!(
+                structure(&fish),
+                structure(&first),
+                "detail seed {}",
+                detail_seed
+            );
+            assert_ne!(fish.render(), first.render(), "detail seed {}", detail_seed);
+        }
+    }
+
+    #[test]
+    fn memory_estimates_match_the_textures_grown() {
+        let bytes = |map: &types::PixelMap| {
+            let header = std::mem::size_of::<Vec<f64>>();
+            let rows = map
+                .iter()
+                .map(|row| row.capacity() * std::mem::size_of::<f64>());
+            header + map.capacity() * header + rows.sum::<usize>()
+        };
+        for seed in 0..4 {
+            let fish = seeded_fish(seed, 96, 64);
+            let layers = &fish.layers;
+            let masks = layers.iter().filter_map(|layer| layer.mask.as_ref());
+            let actual: usize = layers
+                .iter()
+                .map(|layer| bytes(&layer.image))
+                .sum::<usize>()
+                + masks.clone().map(bytes).sum::<usize>();
+            let share = masks.count() as f64 / layers.len() as f64;
+            let estimate = Jelatofish::estimate_memory(fish.size, layers.len(), share);
+            let error = (estimate as f64 - actual as f64).abs() / actual as f64;
+            assert!(error < 0.01, "{} estimated, {} grown", estimate, actual);
+        }
+    }
+
+    #[test]
+    fn standard_alpha_composites_like_a_backdrop_layer() {
+        let (size, backdrop) = (types::Area::new(16, 8), Colour::new(0.2, 0.7, 0.4, 1.0));
+        for seed in 0..4 {
+            let mut fish = seeded_fish(seed, size.width, size.height);
+            let straight = fish.render_standard_alpha();
+            assert!(
+                straight.chunks(4).any(|pixel| pixel[3] < 255),
+                "seed {}",
+                seed
+            );
+            //The layers composite onto one more layer behind them, covering everything.
+            let mut behind = seeded_fish(seed + 1, size.width, size.height)
+                .layers
+                .remove(0);
+            behind.image = vec![vec![0.0; size.width]; size.height];
+            behind.mask = Some(vec![vec![1.0; size.width]; size.height]);
+            behind.invert_mask = false;
+            behind.fore = backdrop;
+            behind.back = backdrop;
+            fish.layers.push(behind);
+            //The colour, its alpha and the layered result are each rounded by half a step.
+            for (pixel, inside) in straight.chunks(4).zip(fish.render().chunks(4)) {
+                let alpha = pixel[3] as f64 / 255.0;
+                let backdrop = channels(&backdrop);
+                for channel in 0..3 {
+                    let over =
+                        pixel[channel] as f64 * alpha + backdrop[channel] * 255.0 * (1.0 - alpha);
+                    assert!(
+                        (over - inside[channel] as f64).abs() <= 1.5,
+                        "seed {}",
+                        seed
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "entropy")]
+    fn indexed_fish_keep_to_their_colours() {
+        let path = std::env::temp_dir().join("jelatofish-indexed-test.png");
+        let filename = path.to_str().unwrap();
+        for &max_colours in &[1, 4, 16] {
+            save_fish_indexed(32, 24, filename, max_colours).unwrap();
+            let mut decoder = png::Decoder::new(std::fs::File::open(&path).unwrap());
+            //Leave the indices as they are instead of expanding them to colours.
+            decoder.set_transformations(png::Transformations::IDENTITY);
+            let (info, mut reader) = decoder.read_info().unwrap();
+            let mut indices = vec![0; info.buffer_size()];
+            reader.next_frame(&mut indices).unwrap();
+            let palette = reader.info().palette.as_ref().unwrap();
+            assert!(
+                palette.len() <= max_colours * 3,
+                "{} colours",
+                palette.len() / 3
+            );
+            let used: std::collections::HashSet<_> = indices.iter().collect();
+            assert!(used.len() <= max_colours);
+            assert!(used
+                .iter()
+                .all(|&&index| usize::from(index) * 3 < palette.len()));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "entropy")]
+    fn cancelled_fish_stop_before_the_first_layer() {
+        let palette = ColourPalette::from_hex(&["#f00", "#0f0", "#00f"]).unwrap();
+        let cancel = AtomicBool::new(true);
+        //Six layers this size take seconds to draw, so a quick answer means none were.
+        let start = std::time::Instant::now();
+        let fish = Jelatofish::random_cancellable(
+            types::Area::new(2048, 2048),
+            &palette,
+            Some(6),
+            None,
+            &cancel,
+        );
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        assert_eq!(fish.err(), Some("cancelled".to_string()));
+        cancel.store(false, Ordering::Relaxed);
+        let fish = Jelatofish::random_cancellable(
+            types::Area::new(8, 8),
+            &palette,
+            Some(6),
+            None,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(fish.layers.len(), 6);
+    }
+
+    #[test]
+    fn fish_with_nothing_but_zero_masks_are_blank() {
+        let mut fish = seeded_fish(1, 16, 16);
+        assert!(!fish.is_blank());
+        for layer in &mut fish.layers {
+            layer.mask = Some(vec![vec![0.0; 16]; 16]);
+            layer.invert_mask = false;
+        }
+        assert!(fish.is_blank());
+        //One layer showing through at one of the pixels looked at is enough.
+        fish.layers[0].mask.as_mut().unwrap()[8][8] = 1.0;
+        assert!(!fish.is_blank());
+    }
+
+    #[test]
+    fn values_on_a_stop_take_its_colour() {
+        let (size, generator) = (types::Area::new(8, 4), generators::Generators::Coswave);
+        let params: generators::GeneratorParams = game::GameRng::seed_from_u64(7).gen();
+        let mut rng = game::GameRng::seed_from_u64(7);
+        let field = generators::generate(size, &generator, &params, &mut rng);
+        let (first, second) = (field[0][0], field[2][5]);
+        assert_ne!(first, second);
+        //Put a stop right on two of the values the same seed is going to give.
+        let ramp = [
+            (first, Colour::new(1.0, 0.0, 0.0, 1.0)),
+            (second, Colour::new(0.0, 0.0, 1.0, 1.0)),
+        ];
+        let mut rng = game::GameRng::seed_from_u64(7);
+        let bytes = gradient_map_with_rng(size, &generator, &params, &ramp, &mut rng);
+        let pixel = |x: usize, y: usize| &bytes[(y * size.width + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(5, 2), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn unit_gammas_change_nothing_and_a_high_red_one_darkens_reds() {
+        let colour = Colour::new(0.6, 0.3, 0.9, 0.5);
+        assert_eq!(
+            channels(&colour.apply_channel_gamma(1.0, 1.0, 1.0)),
+            channels(&colour)
+        );
+        let graded = colour.apply_channel_gamma(2.2, 1.0, 1.0);
+        assert!(graded.red < colour.red);
+        assert_eq!(
+            [graded.green, graded.blue, graded.alpha],
+            [colour.green, colour.blue, colour.alpha]
+        );
+        let fish = seeded_fish(4, 16, 16);
+        let plain = fish.render_ordered(ChannelOrder::Rgba);
+        assert_eq!(
+            fish.render_graded(ChannelOrder::Rgba, Some([1.0, 1.0, 1.0])),
+            plain
+        );
+        let warm = fish.render_graded(ChannelOrder::Rgba, Some([2.2, 1.0, 1.0]));
+        for (graded, plain) in warm.chunks(4).zip(plain.chunks(4)) {
+            assert!(graded[0] <= plain[0]);
+            assert_eq!(graded[1..], plain[1..]);
+        }
+        assert!(warm
+            .chunks(4)
+            .zip(plain.chunks(4))
+            .any(|(g, p)| g[0] < p[0]));
+    }
+
+    #[test]
+    fn loose_searches_find_a_seed_in_time() {
+        let size = types::Area::new(16, 16);
+        let palette = ColourPalette::from_hex(&["#000", "#fff", "#f80", "#08f"]).unwrap();
+        let seed = Jelatofish::find_seed(size, &palette, 0.5, 0.25, 16).unwrap();
+        assert!(seed < 16);
+        let fish = Jelatofish::random_seeded(seed, size, &palette, None, None).unwrap();
+        assert!((fish.mean_luminance(false) - 0.5).abs() <= 0.25);
+        //Nothing is brighter than white.
+        assert_eq!(Jelatofish::find_seed(size, &palette, 2.0, 0.25, 4), None);
+    }
+
+    #[test]
+    fn the_alpha_of_layer_colours_is_ignored() {
+        let opaque: Colour = Colour::random_opaque_with(&mut game::GameRng::seed_from_u64(3));
+        let plain: Colour = game::GameRng::seed_from_u64(3).gen();
+        assert!(opaque.same_rgb(&plain));
+        assert_eq!((opaque.alpha, plain.alpha), (1.0, 0.0));
+        //Only the masks decide how much of a layer shows, so any alpha draws the same fish.
+        let mut fish = seeded_fish(6, 16, 16);
+        let before = fish.render();
+        for layer in &mut fish.layers {
+            layer.fore.alpha = 0.25;
+            layer.back.alpha = 0.75;
+        }
+        assert_eq!(fish.render(), before);
+    }
+
+    #[test]
+    fn near_identical_colours_collapse_to_the_first() {
+        let mut palette = ColourPalette::from_hex(&["#800000", "#810000", "#008000"]).unwrap();
+        palette.dedup(0.01);
+        let kept: Vec<_> = palette.colours.iter().map(channels).collect();
+        assert_eq!(
+            kept,
+            [
+                [128.0 / 255.0, 0.0, 0.0, 1.0],
+                [0.0, 128.0 / 255.0, 0.0, 1.0]
+            ]
+        );
+        //A threshold of nothing keeps everything, even exact repeats.
+        let mut repeats = ColourPalette::from_hex(&["#800000", "#800000"]).unwrap();
+        repeats.dedup(0.0);
+        assert_eq!(repeats.colours.len(), 2);
+    }
+
+    #[test]
+    fn mostly_red_fish_have_a_red_hue() {
+        //Reds from either side of zero, so a plain average of the hues would come out cyan.
+        let reds = ColourPalette::from_hex(&["#ff0018", "#ff1800", "#e00010", "#e01000"]).unwrap();
+        for seed in 0..4 {
+            let fish = Jelatofish::random_seeded(seed, types::Area::new(16, 16), &reds, None, None)
+                .unwrap();
+            let (hue, saturation, _) = fish.mean_hsv();
+            let off_red = hue.min(1.0 - hue);
+            assert!(off_red < 0.05, "seed {}: hue {}", seed, hue);
+            assert!(saturation > 0.5, "seed {}: saturation {}", seed, saturation);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "entropy")]
+    fn ora_files_hold_a_png_per_layer() {
+        let path = std::env::temp_dir().join("jelatofish-layers-test.ora");
+        let filename = path.to_str().unwrap();
+        for seed in 0..3 {
+            let size = types::Area::new(24, 16);
+            let layers = game::with_seed(seed, || {
+                Jelatofish::random(size, &Default::default(), None, None)
+                    .unwrap()
+                    .layers
+                    .len()
+            });
+            game::with_seed(seed, || save_fish_ora(24, 16, filename)).unwrap();
+            let mut zip = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+            assert_eq!(zip.by_index(0).unwrap().name(), "mimetype");
+            let mut stack = String::new();
+            std::io::Read::read_to_string(&mut zip.by_name("stack.xml").unwrap(), &mut stack)
+                .unwrap();
+            assert_eq!(stack.matches("<layer ").count(), layers);
+            for i in 0..layers {
+                let layer = zip.by_name(&format!("data/layer{}.png", i)).unwrap();
+                let (info, _) = png::Decoder::new(layer).read_info().unwrap();
+                assert_eq!((info.width, info.height), (24, 16));
+            }
+            assert!(zip.by_name(&format!("data/layer{}.png", layers)).is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn shared_blobs_grow_the_same_pixels() {
+        let first = fish_from_params("#seed=7&w=16&h=8").unwrap();
+        assert_eq!(first.len(), 16 * 8 * 4);
+        let blob = current_params().unwrap();
+        assert_eq!(fish_from_params(&blob).unwrap(), first);
+        assert_eq!(fish_from_params(&format!("#{}", blob)).unwrap(), first);
+        assert_eq!(current_params().unwrap(), blob);
+        for bad in &[
+            "seed=7&w=0",
+            "seed=7&h=0",
+            "seed=7&w=-1",
+            "w=16",
+            "seed=7&depth=3",
+        ] {
+            assert!(fish_from_params(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn averaged_previews_of_busy_fish_are_smoother() {
+        //Ripples three pixels apart, far finer than a preview pixel.
+        let mut fish = seeded_fish(1, 256, 256);
+        let ripples: Vec<Vec<_>> = (0..256)
+            .map(|y| {
+                (0..256)
+                    .map(|x| ((x + y) as f64 * 2.0 * std::f64::consts::PI / 3.0).cos() / 2.0 + 0.5)
+                    .collect()
+            })
+            .collect();
+        for (index, layer) in fish.layers.iter_mut().enumerate() {
+            layer.image = ripples.clone();
+            layer.mask = Some(vec![vec![if index == 0 { 1.0 } else { 0.0 }; 256]; 256]);
+            layer.invert_mask = false;
+            layer.fore = Colour::new(1.0, 1.0, 1.0, 1.0);
+            layer.back = Colour::new(0.0, 0.0, 0.0, 1.0);
+        }
+        let variance = |block| {
+            let preview = fish.render_preview(32, 32, block).unwrap();
+            let shades: Vec<f64> = preview.chunks(4).map(|pixel| pixel[0] as f64).collect();
+            let mean = shades.iter().sum::<f64>() / shades.len() as f64;
+            shades.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / shades.len() as f64
+        };
+        let (pointed, averaged) = (variance(1), variance(4));
+        assert!(averaged < pointed / 4.0, "{} against {}", averaged, pointed);
+        assert!(fish.render_preview(0, 32, 4).is_err());
+        assert!(fish.render_preview(32, 0, 4).is_err());
+    }
+
+    #[test]
+    fn the_last_frame_is_the_render_and_the_first_is_the_front_layer() {
+        let fish = seeded_fish(4, 16, 16);
+        assert!(fish.layers.len() > 1);
+        let frames = fish.build_up_frames();
+        assert_eq!(frames.len(), fish.layers.len());
+        assert_eq!(frames.last(), Some(&fish.render()));
+        //The same seed grows the same fish, so cut it down to its front layer.
+        let mut front = seeded_fish(4, 16, 16);
+        front.layers.truncate(1);
+        assert_eq!(frames[0], front.render());
+        assert_ne!(frames[0], fish.render());
+    }
+
+    #[test]
+    #[cfg(feature = "entropy")]
+    fn wide_test_images_keep_their_rows_as_rows() {
+        let path = std::env::temp_dir().join("jelatofish-wide-test.png");
+        let (width, height, generator) = (24, 8, generators::Generators::Coswave);
+        game::with_seed(5, || {
+            save_test_image(width, height, generator, path.to_str().unwrap())
+        });
+        //The same seed draws the same params and roll again.
+        let expected = game::with_seed(5, || {
+            let params = game::random();
+            let area = types::Area::new(width, height);
+            generators::generate(area, &generator, &params, &mut game::get_rng())
+        });
+        let saved = image::open(&path).unwrap().to_rgb8();
+        assert_eq!(saved.dimensions(), (width as u32, height as u32));
+        for (x, y, pixel) in saved.enumerate_pixels() {
+            let value = types::to_chanval(expected[y as usize][x as usize]);
+            assert_eq!(pixel.0, [value; 3], "at ({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn single_colour_palettes_paint_the_fish_in_that_colour() {
+        //Every layer gets the one colour and its nudge towards grey, 1/32 or 8 levels away.
+        let ranges = [0x33..=0x33 + 8, 0x66..=0x66 + 8, 0x99 - 8..=0x99];
+        for seed in 0..4 {
+            let pixels =
+                new_fish_image_with_palette(32, 32, seed, vec!["#336699".to_string()]).unwrap();
+            for pixel in pixels.chunks(4) {
+                assert_eq!(pixel[3], 255);
+                for (channel, range) in pixel.iter().zip(&ranges) {
+                    assert!(range.contains(channel), "seed {} gives {:?}", seed, pixel);
+                }
+            }
+        }
+        assert!(new_fish_image_with_palette(8, 8, 0, vec!["#3366".to_string()]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "entropy")]
+    fn compact_pngs_are_smaller_than_plain_ones() {
+        let (width, height) = (128, 96);
+        let rgba = seeded_fish(2, width, height).render();
+        let compact = encode_png_compact(width, height, &rgba).unwrap();
+        //What save_fish_image writes for the same fish.
+        let mut plain = Vec::new();
+        let rgb: Vec<u8> = rgba
+            .chunks(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect();
+        image::DynamicImage::ImageRgb8(
+            image::RgbImage::from_raw(width as u32, height as u32, rgb.clone()).unwrap(),
+        )
+        .write_to(&mut plain, image::ImageOutputFormat::Png)
+        .unwrap();
+        assert!(
+            compact.len() < plain.len(),
+            "{} >= {} bytes",
+            compact.len(),
+            plain.len()
+        );
+        //And nothing was lost on the way.
+        let read = image::load_from_memory(&compact).unwrap().to_rgb8();
+        assert_eq!(read.into_raw(), rgb);
+    }
+
+    #[test]
+    #[cfg(feature = "entropy")]
+    fn the_same_seed_saves_byte_identical_files() {
+        let path = |name: &str| std::env::temp_dir().join(format!("jelatofish-seed-{}.png", name));
+        let save = |seed: u64, name: &str| {
+            game::with_seed(seed, || {
+                save_fish_image(48, 32, path(name).to_str().unwrap())
+            });
+            std::fs::read(path(name)).unwrap()
+        };
+        let (first, again, other) = (save(9, "first"), save(9, "again"), save(10, "other"));
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        //And the seeded constructor grows the very same fish.
+        let fish = Jelatofish::random_with_seed(
+            types::Area::new(48, 32),
+            &Default::default(),
+            None,
+            None,
+            9,
+        )
+        .unwrap();
+        let rgb: Vec<u8> = fish
+            .render()
+            .chunks(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect();
+        let saved = image::load_from_memory(&first).unwrap().to_rgb8();
+        assert_eq!(saved.into_raw(), rgb);
+    }
+
+    #[test]
+    fn rgba8_buffers_hold_every_pixel_with_its_alpha() {
+        let fish = seeded_fish(8, 12, 10);
+        let mut buf = vec![0; 12 * 10 * 4];
+        fish.render_to_rgba8(&mut buf).unwrap();
+        for (i, pixel) in buf.chunks(4).enumerate() {
+            let p = fish.get_pixel_val(i % 12, i / 12).unwrap();
+            let [r, g, b] = p.to_rgb8();
+            assert_eq!(pixel, [r, g, b, types::to_chanval(p.alpha)]);
+        }
+        //The colours are the ones render gives; only the alpha is the composite's.
+        let rgb = |bytes: &[u8]| -> Vec<u8> {
+            bytes
+                .chunks(4)
+                .flat_map(|pixel| pixel[..3].to_vec())
+                .collect()
+        };
+        assert_eq!(rgb(&buf), rgb(&fish.render()));
+        assert!(fish.render_to_rgba8(&mut vec![0; 12 * 10 * 4 - 1]).is_err());
+        assert!(fish.render_to_rgba8(&mut []).is_err());
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_merge() {
+        //Run with cargo test --release -- --ignored --nocapture to see the timings.
+        let fish = JelatofishBuilder::new()
+            .size(types::Area::new(1024, 1024))
+            .layers(6)
+            .seed(0)
+            .build()
+            .unwrap();
+        let time = |name: &str, merge: &dyn Fn(usize, usize) -> Colour| {
+            let start = std::time::Instant::now();
+            let mut total = 0.0;
+            for y in 0..1024 {
+                for x in 0..1024 {
+                    total += merge(x, y).red;
+                }
+            }
+            println!("{}: {:?} ({})", name, start.elapsed(), total);
+        };
+        for _ in 0..3 {
+            time("colours", &|x, y| merge_with_colours(&fish, x, y));
+            time("scalars", &|x, y| fish.merge_pixel(x, y, fish.layers.len()));
+        }
+    }
+}
@@ -20,6 +20,31 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 
 */
 
+use jelatofish::generators::Generators;
+
 fn main() {
-    jelatofish::save_fish_image(256, 256, "image.png");
+    /*
+    With no arguments we draw a whole fish.
+    Passing --generator <name> draws a single greyscale texture from that
+    generator instead, which is handy for looking at one generator alone.
+    */
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().position(|arg| arg == "--generator") {
+        Some(i) => {
+            let name = args.get(i + 1).map(String::as_str).unwrap_or_default();
+            match Generators::from_name(name) {
+                Some(generator) => jelatofish::save_test_image(256, 256, generator, "image.png"),
+                None => {
+                    let names: Vec<_> = Generators::every().iter().map(Generators::name).collect();
+                    eprintln!(
+                        "unknown generator: {:?} (try one of {})",
+                        name,
+                        names.join(", ")
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => jelatofish::save_fish_image(256, 256, "image.png"),
+    }
 }
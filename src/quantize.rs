@@ -0,0 +1,102 @@
+/*
+
+Copyright ©2021 Amane Katagiri
+Copyright ©1999 Mars Saxman
+All Rights Reserved
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, write to the Free Software
+Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
+
+*/
+
+/*
+Tools for cutting a picture down to a small palette of colours, for
+indexed or retro-looking output. Colours are plain 8-bit RGB triples.
+*/
+
+pub fn median_cut(pixels: &[[u8; 3]], max_colours: usize) -> Vec<[u8; 3]> {
+    /*
+    Pick at most max_colours colours that stand in well for these pixels.
+    We start with every pixel in one box. Then, over and over, we take the
+    box that spreads furthest along any one channel, sort it along that
+    channel and cut it in half at the median. Once we have enough boxes, or
+    no box has more than one colour left in it, each box becomes the
+    average of its pixels.
+    */
+    let mut boxes = vec![pixels.to_vec()];
+    while boxes.len() < max_colours {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, colours)| (index, widest_channel(colours)))
+            .filter(|(_, (_, spread))| *spread > 0)
+            .max_by_key(|(_, (_, spread))| *spread);
+        let (index, (channel, _)) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+        let mut lower = boxes.swap_remove(index);
+        lower.sort_unstable_by_key(|colour| colour[channel]);
+        let upper = lower.split_off(lower.len() / 2);
+        boxes.push(lower);
+        boxes.push(upper);
+    }
+    boxes
+        .iter()
+        .filter(|colours| !colours.is_empty())
+        .map(|colours| average(colours))
+        .collect()
+}
+
+pub fn nearest(palette: &[[u8; 3]], colour: [u8; 3]) -> Option<usize> {
+    //Find the palette entry closest to this colour, by plain RGB distance.
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| {
+            entry
+                .iter()
+                .zip(colour.iter())
+                .map(|(&a, &b)| (i32::from(a) - i32::from(b)).pow(2))
+                .sum::<i32>()
+        })
+        .map(|(index, _)| index)
+}
+
+fn widest_channel(colours: &[[u8; 3]]) -> (usize, u8) {
+    //Which channel do these colours spread furthest along, and how far?
+    (0..3)
+        .map(|channel| {
+            let values = colours.iter().map(|colour| colour[channel]);
+            let spread = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, spread)
+        })
+        .max_by_key(|(_, spread)| *spread)
+        .unwrap()
+}
+
+fn average(colours: &[[u8; 3]]) -> [u8; 3] {
+    let mut sums = [0_u64; 3];
+    for colour in colours {
+        for (sum, &value) in sums.iter_mut().zip(colour.iter()) {
+            *sum += u64::from(value);
+        }
+    }
+    let count = colours.len() as u64;
+    let mut out = [0; 3];
+    for (out, sum) in out.iter_mut().zip(sums.iter()) {
+        *out = ((sum + count / 2) / count) as u8;
+    }
+    out
+}
@@ -0,0 +1,410 @@
+/*
+
+Copyright ©2021 Amane Katagiri
+Copyright ©1999 Mars Saxman
+All Rights Reserved
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, write to the Free Software
+Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
+
+*/
+
+/*
+Just enough of a random number library to grow fish: the generator, the
+traits and the ways of turning raw numbers into floats and ranges that rand
+0.8 and rand_pcg 0.3 use. Every draw is bit-for-bit the same as theirs, so
+every seed keeps growing the fish it always has.
+The one place they part ways is that nothing here panics. Where rand would
+refuse to sample (an empty range, or one too wide for its type) these
+just hand back the low end of the range.
+*/
+
+pub trait RngCore {
+    fn next_u32(&mut self) -> u32;
+    fn next_u64(&mut self) -> u64;
+    fn fill_bytes(&mut self, dest: &mut [u8]);
+}
+impl<R: RngCore + ?Sized> RngCore for &mut R {
+    fn next_u32(&mut self) -> u32 {
+        (**self).next_u32()
+    }
+    fn next_u64(&mut self) -> u64 {
+        (**self).next_u64()
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        (**self).fill_bytes(dest)
+    }
+}
+
+pub trait Rng: RngCore {
+    fn gen<T>(&mut self) -> T
+    where
+        distributions::Standard: distributions::Distribution<T>,
+    {
+        distributions::Distribution::sample(&distributions::Standard, self)
+    }
+
+    fn gen_range<T, R>(&mut self, range: R) -> T
+    where
+        T: distributions::uniform::SampleUniform,
+        R: distributions::uniform::SampleRange<T>,
+    {
+        range.sample_single(self)
+    }
+}
+impl<R: RngCore + ?Sized> Rng for R {}
+
+pub trait SeedableRng: Sized {
+    type Seed: Default + AsMut<[u8]>;
+
+    fn from_seed(seed: Self::Seed) -> Self;
+
+    fn seed_from_u64(mut state: u64) -> Self {
+        //Spread the u64 over the whole seed with PCG32, the same way rand_core does.
+        fn pcg32(state: &mut u64) -> [u8; 4] {
+            *state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(11634580027462260723);
+            let xorshifted = (((*state >> 18) ^ *state) >> 27) as u32;
+            xorshifted.rotate_right((*state >> 59) as u32).to_le_bytes()
+        }
+        let mut seed = Self::Seed::default();
+        for chunk in seed.as_mut().chunks_mut(4) {
+            let len = chunk.len();
+            chunk.copy_from_slice(&pcg32(&mut state)[..len]);
+        }
+        Self::from_seed(seed)
+    }
+
+    fn from_rng<R: RngCore>(mut rng: R) -> Self {
+        //Unlike rand's, this can't fail: none of our sources ever runs dry.
+        let mut seed = Self::Seed::default();
+        rng.fill_bytes(seed.as_mut());
+        Self::from_seed(seed)
+    }
+}
+
+fn fill_bytes_from_u64<R: RngCore + ?Sized>(rng: &mut R, dest: &mut [u8]) {
+    for chunk in dest.chunks_mut(8) {
+        let len = chunk.len();
+        chunk.copy_from_slice(&rng.next_u64().to_le_bytes()[..len]);
+    }
+}
+
+/*
+PCG XSL RR 128/64, the generator rand_pcg calls Pcg64: a 128-bit LCG whose
+state is folded down to 64 bits and rotated for each number it hands out.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pcg64 {
+    state: u128,
+    increment: u128,
+}
+impl Pcg64 {
+    const MULTIPLIER: u128 = 0x2360_ED05_1FC6_5DA4_4385_DF64_9FCC_F645;
+
+    fn step(&mut self) {
+        self.state = self
+            .state
+            .wrapping_mul(Pcg64::MULTIPLIER)
+            .wrapping_add(self.increment);
+    }
+}
+impl RngCore for Pcg64 {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+    fn next_u64(&mut self) -> u64 {
+        self.step();
+        let rotation = (self.state >> 122) as u32;
+        (((self.state >> 64) as u64) ^ (self.state as u64)).rotate_right(rotation)
+    }
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill_bytes_from_u64(self, dest)
+    }
+}
+impl SeedableRng for Pcg64 {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> Self {
+        let mut words = [0_u64; 4];
+        for (word, bytes) in words.iter_mut().zip(seed.chunks(8)) {
+            let mut le = [0_u8; 8];
+            le.copy_from_slice(bytes);
+            *word = u64::from_le_bytes(le);
+        }
+        let state = u128::from(words[0]) | u128::from(words[1]) << 64;
+        let increment = u128::from(words[2]) | u128::from(words[3]) << 64;
+        //The increment has to be odd.
+        let mut rng = Pcg64 {
+            state,
+            increment: increment | 1,
+        };
+        rng.state = rng.state.wrapping_add(rng.increment);
+        rng.step();
+        rng
+    }
+}
+
+pub mod distributions {
+    use super::Rng;
+
+    pub trait Distribution<T> {
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> T;
+    }
+
+    //Plain numbers over their whole range, or floats in [0, 1).
+    #[derive(Debug, Clone, Copy)]
+    pub struct Standard;
+
+    impl Distribution<u32> for Standard {
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u32 {
+            rng.next_u32()
+        }
+    }
+    impl Distribution<u64> for Standard {
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+            rng.next_u64()
+        }
+    }
+    impl Distribution<usize> for Standard {
+        #[cfg(target_pointer_width = "64")]
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+            rng.next_u64() as usize
+        }
+        #[cfg(not(target_pointer_width = "64"))]
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+            rng.next_u32() as usize
+        }
+    }
+    impl Distribution<i32> for Standard {
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> i32 {
+            rng.next_u32() as i32
+        }
+    }
+    impl Distribution<i64> for Standard {
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> i64 {
+            rng.next_u64() as i64
+        }
+    }
+    impl Distribution<bool> for Standard {
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> bool {
+            //The top bit, as the low bits of weaker generators can fall into patterns.
+            (rng.next_u32() as i32) < 0
+        }
+    }
+    impl Distribution<f32> for Standard {
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f32 {
+            (rng.next_u32() >> 8) as f32 * (1.0 / (1_u32 << 24) as f32)
+        }
+    }
+    impl Distribution<f64> for Standard {
+        fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+            (rng.next_u64() >> 11) as f64 * (1.0 / (1_u64 << 53) as f64)
+        }
+    }
+
+    pub mod uniform {
+        use super::super::Rng;
+        use std::cmp::Ordering;
+        use std::ops::{Range, RangeInclusive};
+
+        pub trait SampleUniform: Copy + PartialOrd {
+            //Both are only called with low < high, or low <= high when inclusive.
+            fn sample_single<R: Rng + ?Sized>(low: Self, high: Self, rng: &mut R) -> Self;
+            fn sample_single_inclusive<R: Rng + ?Sized>(low: Self, high: Self, rng: &mut R)
+                -> Self;
+        }
+
+        pub trait SampleRange<T> {
+            fn sample_single<R: Rng + ?Sized>(self, rng: &mut R) -> T;
+        }
+        impl<T: SampleUniform> SampleRange<T> for Range<T> {
+            fn sample_single<R: Rng + ?Sized>(self, rng: &mut R) -> T {
+                match self.start.partial_cmp(&self.end) {
+                    Some(Ordering::Less) => T::sample_single(self.start, self.end, rng),
+                    _ => self.start,
+                }
+            }
+        }
+        impl<T: SampleUniform> SampleRange<T> for RangeInclusive<T> {
+            fn sample_single<R: Rng + ?Sized>(self, rng: &mut R) -> T {
+                let (low, high) = self.into_inner();
+                match low.partial_cmp(&high) {
+                    Some(Ordering::Less | Ordering::Equal) => {
+                        T::sample_single_inclusive(low, high, rng)
+                    }
+                    _ => low,
+                }
+            }
+        }
+
+        macro_rules! uniform_int_impl {
+            ($ty:ty, $unsigned:ty, $large:ty, $wide:ty) => {
+                impl SampleUniform for $ty {
+                    fn sample_single<R: Rng + ?Sized>(low: $ty, high: $ty, rng: &mut R) -> $ty {
+                        Self::sample_single_inclusive(low, high - 1, rng)
+                    }
+                    fn sample_single_inclusive<R: Rng + ?Sized>(
+                        low: $ty,
+                        high: $ty,
+                        rng: &mut R,
+                    ) -> $ty {
+                        /*
+                        Widening multiply, throwing away the few draws that
+                        would land unevenly, with the same cheap rejection
+                        zone as rand's single samples.
+                        */
+                        let range = high.wrapping_sub(low).wrapping_add(1) as $unsigned as $large;
+                        if range == 0 {
+                            //The whole type, so any number will do.
+                            return rng.gen();
+                        }
+                        let zone = (range << range.leading_zeros()).wrapping_sub(1);
+                        loop {
+                            let v: $large = rng.gen();
+                            let wide = v as $wide * range as $wide;
+                            if wide as $large <= zone {
+                                return low.wrapping_add((wide >> <$large>::BITS) as $ty);
+                            }
+                        }
+                    }
+                }
+            };
+        }
+        uniform_int_impl! { i32, u32, u32, u64 }
+        uniform_int_impl! { u32, u32, u32, u64 }
+        uniform_int_impl! { i64, u64, u64, u128 }
+        uniform_int_impl! { u64, u64, u64, u128 }
+        #[cfg(target_pointer_width = "64")]
+        uniform_int_impl! { usize, usize, usize, u128 }
+        #[cfg(not(target_pointer_width = "64"))]
+        uniform_int_impl! { usize, usize, usize, u64 }
+
+        trait IntoUnit {
+            type Float;
+            fn into_unit(self) -> Self::Float;
+        }
+
+        macro_rules! uniform_float_impl {
+            ($ty:ty, $uty:ty, $discard:expr, $fraction:expr, $bias:expr) => {
+                impl IntoUnit for $uty {
+                    type Float = $ty;
+                    fn into_unit(self) -> $ty {
+                        //The top bits as the fraction of a float in [1, 2), moved down to [0, 1).
+                        <$ty>::from_bits((self >> $discard) | ($bias << $fraction)) - 1.0
+                    }
+                }
+                impl SampleUniform for $ty {
+                    fn sample_single<R: Rng + ?Sized>(low: $ty, high: $ty, rng: &mut R) -> $ty {
+                        let scale = high - low;
+                        if !scale.is_finite() {
+                            return low;
+                        }
+                        loop {
+                            let value = rng.gen::<$uty>().into_unit() * scale + low;
+                            if value < high {
+                                return value;
+                            }
+                        }
+                    }
+                    fn sample_single_inclusive<R: Rng + ?Sized>(
+                        low: $ty,
+                        high: $ty,
+                        rng: &mut R,
+                    ) -> $ty {
+                        //Shrink the scale until even the largest draw stays within high.
+                        let max_rand = <$uty>::MAX.into_unit();
+                        let mut scale = (high - low) / max_rand;
+                        if !scale.is_finite() {
+                            return low;
+                        }
+                        while scale * max_rand + low > high {
+                            scale = <$ty>::from_bits(scale.to_bits() - 1);
+                        }
+                        rng.gen::<$uty>().into_unit() * scale + low
+                    }
+                }
+            };
+        }
+        uniform_float_impl! { f32, u32, 32 - 23, 23, 127 }
+        uniform_float_impl! { f64, u64, 64 - 52, 52, 1023 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng as _, SeedableRng as _};
+
+    #[test]
+    fn draws_match_rand_and_rand_pcg() {
+        /*
+        Every kind of draw the fish make, side by side with the libraries
+        this module stands in for. Floats are compared bit for bit; any
+        difference at all means old seeds would grow new fish.
+        */
+        for seed in 0..64 {
+            let mut ours = Pcg64::seed_from_u64(seed);
+            let mut theirs = rand_pcg::Pcg64::seed_from_u64(seed);
+            for i in 0..64_u32 {
+                let (wide, step) = (f64::from(i) * 7.5, i as i32);
+                assert_eq!(ours.next_u64(), rand::RngCore::next_u64(&mut theirs));
+                assert_eq!(ours.gen::<u32>(), theirs.gen::<u32>());
+                assert_eq!(ours.gen::<bool>(), theirs.gen::<bool>());
+                assert_eq!(ours.gen::<f64>().to_bits(), theirs.gen::<f64>().to_bits());
+                assert_eq!(ours.gen::<f32>().to_bits(), theirs.gen::<f32>().to_bits());
+                assert_eq!(
+                    ours.gen_range(-wide..wide + 0.5).to_bits(),
+                    theirs.gen_range(-wide..wide + 0.5).to_bits()
+                );
+                assert_eq!(
+                    ours.gen_range(0.0..=wide).to_bits(),
+                    theirs.gen_range(0.0..=wide).to_bits()
+                );
+                assert_eq!(
+                    ours.gen_range(0.0_f32..=1.0).to_bits(),
+                    theirs.gen_range(0.0_f32..=1.0).to_bits()
+                );
+                assert_eq!(ours.gen_range(0..step + 1), theirs.gen_range(0..step + 1));
+                assert_eq!(ours.gen_range(-5..=step), theirs.gen_range(-5..=step));
+                assert_eq!(
+                    ours.gen_range(2..=u64::from(i) + 2),
+                    theirs.gen_range(2..=u64::from(i) + 2)
+                );
+                assert_eq!(ours.gen_range(0..=u64::MAX), theirs.gen_range(0..=u64::MAX));
+                assert_eq!(
+                    ours.gen_range(1..=i as usize + 1),
+                    theirs.gen_range(1..=i as usize + 1)
+                );
+            }
+            //And rngs split off another one.
+            let mut ours = Pcg64::from_rng(&mut ours);
+            let mut theirs = rand_pcg::Pcg64::from_rng(&mut theirs).unwrap();
+            assert_eq!(ours.next_u64(), rand::RngCore::next_u64(&mut theirs));
+        }
+    }
+
+    #[test]
+    fn ranges_rand_would_refuse_give_their_low_end() {
+        let mut rng = Pcg64::seed_from_u64(0);
+        assert_eq!(rng.gen_range(1.0..1.0), 1.0);
+        assert_eq!(rng.gen_range(2.0..=1.0), 2.0);
+        assert_eq!(rng.gen_range(-f64::MAX..f64::MAX), -f64::MAX);
+        assert!(rng.gen_range(f64::NAN..1.0).is_nan());
+        let (low, high) = (5_u64, 4);
+        assert_eq!(rng.gen_range(low..high), 5);
+        assert_eq!(rng.gen_range(low..=high), 5);
+    }
+}
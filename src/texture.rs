@@ -0,0 +1,349 @@
+/*
+
+Copyright ©2021 Amane Katagiri
+Copyright ©1999 Mars Saxman
+All Rights Reserved
+
+This program is free software; you can redistribute it and/or
+modify it under the terms of the GNU General Public License
+as published by the Free Software Foundation; either version 2
+of the License, or (at your option) any later version.
+
+This program is distributed in the hope that it will be useful,
+but WITHOUT ANY WARRANTY; without even the implied warranty of
+MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+GNU General Public License for more details.
+
+You should have received a copy of the GNU General Public License
+along with this program; if not, write to the Free Software
+Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
+
+*/
+
+use super::types;
+
+/*
+Tools that work on finished textures: the PixelMaps coming out of the
+generators, laid out as rows, so that map[y][x] is the pixel at x, y.
+Textures are treated as tiles, so anything reaching off one edge wraps
+around to the opposite one.
+*/
+
+fn size_of(map: &types::PixelMap) -> types::Area {
+    types::Area::new(map.first().map_or(0, Vec::len), map.len())
+}
+
+pub fn dominant_angle(map: &types::PixelMap) -> f64 {
+    /*
+    Find the direction the features of this texture mostly run in.
+    We take the gradient at every pixel and build up the structure tensor
+    from it. Its main axis points across the features (the way the values
+    change fastest), so the features themselves run at right angles to it.
+    The angle is in radians from 0 up to but not including PI, measured from
+    the x axis towards increasing y: stripes running along the rows give 0,
+    stripes running down the columns give PI / 2.
+    A texture with no features at all has no direction; we call that 0.
+    */
+    let size = size_of(map);
+    let (mut xx, mut yy, mut xy) = (0.0, 0.0, 0.0);
+    for y in 0..size.height {
+        let up = &map[(y + size.height - 1) % size.height];
+        let down = &map[(y + 1) % size.height];
+        for x in 0..size.width {
+            let left = map[y][(x + size.width - 1) % size.width];
+            let right = map[y][(x + 1) % size.width];
+            let gx = (right - left) / 2.0;
+            let gy = (down[x] - up[x]) / 2.0;
+            xx += gx * gx;
+            yy += gy * gy;
+            xy += gx * gy;
+        }
+    }
+    if xx == 0.0 && yy == 0.0 {
+        return 0.0;
+    }
+    let across = (2.0 * xy).atan2(xx - yy) / 2.0;
+    (across + std::f64::consts::FRAC_PI_2).rem_euclid(std::f64::consts::PI)
+}
+
+pub fn height_to_normal_map(map: &types::PixelMap, strength: f64) -> Vec<u8> {
+    /*
+    Treat the texture as a heightmap and work out which way its surface
+    faces at every pixel, as a tangent-space normal map: three bytes (red,
+    green, blue) per pixel, row by row.
+    The slope is taken with a Sobel filter, reaching round the edges to the
+    opposite side so the normal map tiles exactly like the texture does.
+    The strength scales the slopes: 0.0 is perfectly flat, larger values
+    make the bumps steeper. Each normal runs from -1 to 1 along each axis,
+    which maps onto 0..255, so a flat surface is (128, 128, 255). Red points
+    right, and green points up the image, as OpenGL expects.
+    */
+    let size = size_of(map);
+    let at = |x: usize, y: usize, dx: isize, dy: isize| {
+        let x = (x as isize + dx).rem_euclid(size.width as isize) as usize;
+        let y = (y as isize + dy).rem_euclid(size.height as isize) as usize;
+        map[y][x]
+    };
+    let mut out = Vec::with_capacity(size.width * size.height * 3);
+    for y in 0..size.height {
+        for x in 0..size.width {
+            let gx = (at(x, y, 1, -1) + 2.0 * at(x, y, 1, 0) + at(x, y, 1, 1))
+                - (at(x, y, -1, -1) + 2.0 * at(x, y, -1, 0) + at(x, y, -1, 1));
+            let gy = (at(x, y, -1, 1) + 2.0 * at(x, y, 0, 1) + at(x, y, 1, 1))
+                - (at(x, y, -1, -1) + 2.0 * at(x, y, 0, -1) + at(x, y, 1, -1));
+            //Uphill to the right tilts the normal left; uphill down the image tilts it up.
+            let (nx, ny, nz) = (-gx * strength, gy * strength, 1.0);
+            let length = (nx * nx + ny * ny + nz * nz).sqrt();
+            for n in &[nx, ny, nz] {
+                out.push(types::to_chanval((n / length + 1.0) / 2.0));
+            }
+        }
+    }
+    out
+}
+
+//Which way to fold a texture onto itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorAxis {
+    //The left half is reflected onto the right half.
+    Horizontal,
+    //The top half is reflected onto the bottom half.
+    Vertical,
+    //The top left quarter is reflected onto the other three.
+    Both,
+}
+
+pub fn mirror_map(map: &types::PixelMap, axis: MirrorAxis) -> types::PixelMap {
+    /*
+    Make a symmetric copy of this texture by reflecting one half of it
+    (or one quarter, for Both) onto the rest. Every pixel on the far side
+    of the mirror takes the value of its reflection, so with Horizontal,
+    map[y][x] == map[y][width - 1 - x] for every pixel.
+    The texture no longer wraps seamlessly the way it came out of the
+    generator: its values still meet across the mirror line and across
+    the tile edges, but the slope turns sharply back on itself there, and
+    that crease shows when the tiles are laid side by side.
+    */
+    let size = size_of(map);
+    let (across, down) = match axis {
+        MirrorAxis::Horizontal => (true, false),
+        MirrorAxis::Vertical => (false, true),
+        MirrorAxis::Both => (true, true),
+    };
+    let reflect = |index: usize, length: usize, flip: bool| {
+        if flip && index >= length.div_ceil(2) {
+            length - 1 - index
+        } else {
+            index
+        }
+    };
+    (0..size.height)
+        .map(|y| {
+            let row = &map[reflect(y, size.height, down)];
+            (0..size.width)
+                .map(|x| row[reflect(x, size.width, across)])
+                .collect()
+        })
+        .collect()
+}
+
+/*
+A post-process is any step that takes a finished texture and makes a new
+one from it. Steps can be chained together into a Pipeline.
+*/
+pub trait PostProcess {
+    fn apply(&self, map: &types::PixelMap) -> types::PixelMap;
+}
+
+impl<F: Fn(&types::PixelMap) -> types::PixelMap> PostProcess for F {
+    fn apply(&self, map: &types::PixelMap) -> types::PixelMap {
+        self(map)
+    }
+}
+
+impl PostProcess for MirrorAxis {
+    fn apply(&self, map: &types::PixelMap) -> types::PixelMap {
+        mirror_map(map, *self)
+    }
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    pub steps: Vec<Box<dyn PostProcess>>,
+}
+impl Pipeline {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn then<P: PostProcess + 'static>(mut self, step: P) -> Self {
+        //Add a step to the end of the pipeline.
+        self.steps.push(Box::new(step));
+        self
+    }
+}
+impl PostProcess for Pipeline {
+    fn apply(&self, map: &types::PixelMap) -> types::PixelMap {
+        //Run each step on the output of the one before it, first to last.
+        self.steps
+            .iter()
+            .fold(map.clone(), |map, step| step.apply(&map))
+    }
+}
+
+/*
+Running statistics over a stream of values, built up one value at a time
+so nothing has to be kept around to work them out. The variance uses
+Welford's method, which stays accurate over millions of values where
+summing the squares would lose most of its precision.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct TextureStats {
+    count: usize,
+    mean: f64,
+    //The sum of squared differences from the running mean.
+    spread: f64,
+    min: f64,
+    max: f64,
+}
+impl Default for TextureStats {
+    fn default() -> Self {
+        TextureStats {
+            count: 0,
+            mean: 0.0,
+            spread: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+impl TextureStats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+    pub fn of(map: &types::PixelMap) -> Self {
+        let mut stats = TextureStats::new();
+        for value in map.iter().flatten() {
+            stats.push(*value);
+        }
+        stats
+    }
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.spread += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+    pub fn variance(&self) -> f64 {
+        //The population variance; nothing at all has no spread.
+        if self.count == 0 {
+            0.0
+        } else {
+            self.spread / self.count as f64
+        }
+    }
+    pub fn min(&self) -> f64 {
+        //With nothing pushed yet, min is infinite and max is minus infinity.
+        self.min
+    }
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripes(across: bool) -> types::PixelMap {
+        //Eight pixels from crest to crest, running along the rows or down the columns.
+        let wave = |i: usize| (i as f64 * std::f64::consts::PI / 4.0).cos() / 2.0 + 0.5;
+        (0..16)
+            .map(|y| (0..16).map(|x| wave(if across { y } else { x })).collect())
+            .collect()
+    }
+
+    #[test]
+    fn stripes_point_along_themselves() {
+        let (along_rows, down_columns) = (stripes(true), stripes(false));
+        assert!(dominant_angle(&along_rows).abs() < 1e-9);
+        assert!((dominant_angle(&down_columns) - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert_eq!(dominant_angle(&vec![vec![0.5; 16]; 16]), 0.0);
+    }
+
+    #[test]
+    fn mirrored_maps_match_their_reflections() {
+        //Odd sizes, so the middle row and column reflect onto themselves.
+        let map: types::PixelMap = (0..7)
+            .map(|y| (0..9).map(|x| (x * 7 + y) as f64 / 63.0).collect())
+            .collect();
+        for &axis in &[
+            MirrorAxis::Horizontal,
+            MirrorAxis::Vertical,
+            MirrorAxis::Both,
+        ] {
+            let mirrored = mirror_map(&map, axis);
+            let across = axis != MirrorAxis::Vertical;
+            let down = axis != MirrorAxis::Horizontal;
+            for y in 0..7 {
+                for x in 0..9 {
+                    let value = mirrored[y][x];
+                    if across {
+                        assert_eq!(value, mirrored[y][8 - x], "{:?} at {}, {}", axis, x, y);
+                    }
+                    if down {
+                        assert_eq!(value, mirrored[6 - y][x], "{:?} at {}, {}", axis, x, y);
+                    }
+                    //The half (or quarter) that is reflected is left as it was.
+                    if x <= 4 && y <= 3 {
+                        assert_eq!(value, map[y][x]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pipelines_run_their_steps_in_order() {
+        let map = stripes(true);
+        assert_eq!(Pipeline::new().apply(&map), map);
+        let each = |f: fn(f64) -> f64| {
+            move |map: &types::PixelMap| -> types::PixelMap {
+                map.iter()
+                    .map(|row| row.iter().map(|&v| f(v)).collect())
+                    .collect()
+            }
+        };
+        let halve_then_lift = Pipeline::new()
+            .then(each(|v| v / 2.0))
+            .then(each(|v| v + 0.5));
+        for (row, original) in halve_then_lift.apply(&map).iter().zip(&map) {
+            for (value, original) in row.iter().zip(original) {
+                assert_eq!(*value, original / 2.0 + 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn flat_maps_face_straight_out() {
+        for &height in &[0.0, 0.5, 1.0] {
+            let normals = height_to_normal_map(&vec![vec![height; 5]; 3], 4.0);
+            assert_eq!(normals.len(), 5 * 3 * 3);
+            assert!(normals.chunks(3).all(|normal| normal == [128, 128, 255]));
+        }
+        //Uphill to the right leans the normal left, away from the slope.
+        let ramp: types::PixelMap = (0..4)
+            .map(|_| (0..8).map(|x| x as f64 / 8.0).collect())
+            .collect();
+        let normals = height_to_normal_map(&ramp, 4.0);
+        let middle = &normals[(8 + 4) * 3..][..3];
+        assert!(middle[0] < 128 && middle[1] == 128, "{:?}", middle);
+    }
+}
@@ -23,12 +23,25 @@ Foundation, Inc., 59 Temple Place - Suite 330, Boston, MA  02111-1307, USA.
 // must be 0.0..=1.0
 pub type PixelVal = f64;
 
+pub const MAX_CHANVAL: f64 = 255.0;
+
+pub fn to_chanval(value: PixelVal) -> u8 {
+    /*
+    Convert a pixel value into a byte for export.
+    We round to the nearest step rather than truncating, so every byte value
+    covers an equal slice of the range; truncating would leave 255 to 1.0 alone.
+    Anything out of range is pinned to the nearest end.
+    */
+    (value.clamp(0.0, 1.0) * MAX_CHANVAL).round() as u8
+}
+
 pub type PixelMap = Vec<Vec<f64>>;
 
 #[derive(Debug)]
 #[derive(Default)]
 #[derive(Clone)]
 #[derive(Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Area {
     pub width: usize,
     pub height: usize,
@@ -58,3 +71,33 @@ impl PixelPoint {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generators::{self, coswave};
+
+    #[test]
+    fn slow_gradients_use_every_byte() {
+        /*
+        A coswave runs over 0.5..1.0, so its bytes should be every one of
+        128..=255 with none missing. The rings are about 80 pixels apart, and
+        the pixel centres sit half a pixel off the origin, so no pixel is
+        exactly 1.0: truncating instead of rounding would never give 255.
+        */
+        let wave = coswave::CoswaveParams::default();
+        let mut seen = [false; 256];
+        for y in 0..256 {
+            for x in 0..256 {
+                let point = generators::GeneratorPoint::new(
+                    (x as f64 + 0.5) / 256.0, (y as f64 + 0.5) / 256.0
+                );
+                seen[to_chanval(coswave::generate(point, &wave)) as usize] = true;
+            }
+        }
+        let low = seen.iter().position(|&seen| seen).unwrap();
+        let high = seen.iter().rposition(|&seen| seen).unwrap();
+        assert_eq!((low, high), (128, 255));
+        assert!(seen[low..=high].iter().all(|&seen| seen), "missing bytes in {}..={}", low, high);
+    }
+}